@@ -4,8 +4,12 @@ use tform::{Config, Formatter};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     
-    // Create a default configuration
-    let config = Config::default();
+    // Create a default configuration, also rejoining words split across lines
+    // ("wit\nhin" below); that repair is off by default
+    let config = Config {
+        repair_split_words: true,
+        ..Config::default()
+    };
     let formatter = Formatter::new(config);
 
     // Example input text
@@ -6,10 +6,14 @@ HOW IT WORKS
 
 1. We open "input.txt" using `File::open` and wrap it in a `BufReader`.
 2. We create a `Formatter` with default (or custom) `Config`.
-3. We call `format_to_markdown(reader)` to stream the file contents
-   through the parser, line by line, constructing the final Markdown output.
-4. For HTML, we re-open the file (or you could store the string in memory if you prefer),
-   create another `BufReader`, and call `format_to_html(reader)`.
+3. We call `parse(reader)` once to stream the file contents through the
+   parser, line by line, building a `Document`.
+4. We render that `Document` with `render_markdown` and `render_html`, so
+   both outputs come from a single read of the file.
+
+When only one output is needed, `format_markdown_to(reader, writer)` (or
+`format_html_to`) skips the `Document` entirely and writes each block as soon
+as it is parsed, keeping memory bounded by the largest block.
 
 USAGE:
   cargo run --example streaming
@@ -39,19 +43,12 @@ fn main() -> Result<(), Box<dyn Error>> {
     let config = Config::default();
     let formatter = Formatter::new(config);
 
-    // Convert file contents to Markdown (streamed through BufReader)
-    let markdown_output = formatter.format_to_markdown(reader)?;
-    println!("=== Markdown Output (Streamed) ===\n{}", markdown_output);
+    // Parse the file once (streamed through BufReader)...
+    let doc = formatter.parse(reader)?;
 
-    // If you also want to show HTML output in the same run, you'd need to
-    // re-open the file or buffer it. For simplicity, let's do it here:
-    let file = File::open(file_path)?;
-    let reader = BufReader::new(file);
-
-    // Convert file contents to HTML (streamed through BufReader)
-    let html_output = formatter.format_to_html(reader)?;
-    println!("=== HTML Output (Streamed) ===\n{}", html_output);
+    // ...and render it as often as needed, without re-reading the file
+    println!("=== Markdown Output ===\n{}", formatter.render_markdown(&doc));
+    println!("=== HTML Output ===\n{}", formatter.render_html(&doc));
 
     Ok(())
 }
-
@@ -0,0 +1,321 @@
+/*!
+Command-line argument parsing for the `tform` binary.
+
+Every `Config` field can be overridden from the command line: `--detect-tables`
+/ `--no-detect-tables` for switches, `--indented-code-width 2` for values, and
+`--custom-patterns REGEX` to add a rule. The fields are discovered from the
+serialized default `Config`, so new options are picked up automatically.
+*/
+
+use std::path::PathBuf;
+
+use serde_json::{Map, Value};
+use tform::Config;
+
+/// Output format selected with `--to`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Markdown,
+    Html,
+    Json,
+}
+
+impl Format {
+    fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "markdown" | "md" => Ok(Format::Markdown),
+            "html" => Ok(Format::Html),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown output format '{}' (expected markdown, html or json)", name)),
+        }
+    }
+}
+
+/// How batch mode decides a file is unchanged since its last conversion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipMode {
+    /// The output is newer than the input.
+    Mtime,
+    /// The input's content hash (and the config) match the last run.
+    Hash,
+}
+
+impl SkipMode {
+    fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "mtime" => Ok(SkipMode::Mtime),
+            "hash" => Ok(SkipMode::Hash),
+            _ => Err(format!("unknown skip mode '{}' (expected mtime or hash)", name)),
+        }
+    }
+}
+
+/// A `Config` field set on the command line.
+#[derive(Debug, Clone)]
+pub struct Override {
+    /// Field name, e.g. "detect_tables".
+    pub key: String,
+    pub value: Value,
+}
+
+/// Options for a conversion run.
+#[derive(Debug, Clone)]
+pub struct Args {
+    /// Input files, read in order as one document; empty means stdin.
+    pub inputs: Vec<PathBuf>,
+    /// Output file; `None` means stdout.
+    pub output: Option<PathBuf>,
+    pub to: Format,
+    pub config: Option<PathBuf>,
+    pub overrides: Vec<Override>,
+    /// Batch mode: globs of files to convert (default `*.txt`).
+    pub include: Vec<String>,
+    /// Batch mode: globs of files and directories to leave out.
+    pub exclude: Vec<String>,
+    /// Batch mode: skip files unchanged since the last run.
+    pub skip_unchanged: Option<SkipMode>,
+    /// Batch mode: number of worker threads (default: one per CPU).
+    pub jobs: Option<usize>,
+    /// Watch mode: milliseconds between polls.
+    pub interval: Option<u64>,
+    /// Report inputs whose Markdown output differs from them, instead of converting.
+    pub check: bool,
+    /// Print a unified diff of each input against its Markdown output, instead of converting.
+    pub diff: bool,
+}
+
+impl Args {
+    /// Whether any batch-only option was given.
+    pub fn has_batch_options(&self) -> bool {
+        !self.include.is_empty() || !self.exclude.is_empty() || self.skip_unchanged.is_some() || self.jobs.is_some()
+    }
+}
+
+/// What the command line asks for.
+#[derive(Debug)]
+pub enum Command {
+    Run(Args),
+    /// Convert a directory, then keep converting files as they change.
+    Watch(Args),
+    Help,
+    Version,
+}
+
+/// Parses the arguments (without the program name).
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let fields = config_fields();
+    let mut parsed = Args {
+        inputs: vec![],
+        output: None,
+        to: Format::Markdown,
+        config: None,
+        overrides: vec![],
+        include: vec![],
+        exclude: vec![],
+        skip_unchanged: None,
+        jobs: None,
+        interval: None,
+        check: false,
+        diff: false,
+    };
+
+    let mut args = args.into_iter().peekable();
+    let watch = args.next_if(|arg| arg == "watch").is_some();
+    while let Some(arg) = args.next() {
+        // "--name=value" carries its value inline; otherwise it is the next argument
+        let (name, mut inline) = match arg.split_once('=') {
+            Some((name, value)) if arg.starts_with("--") => (name.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "-t" | "--to" => parsed.to = Format::parse(&take_value(&name, &mut inline, &mut args)?)?,
+            "-o" | "--output" => parsed.output = Some(take_value(&name, &mut inline, &mut args)?.into()),
+            "-c" | "--config" => parsed.config = Some(take_value(&name, &mut inline, &mut args)?.into()),
+            "--include" => parsed.include.push(take_value(&name, &mut inline, &mut args)?),
+            "--exclude" => parsed.exclude.push(take_value(&name, &mut inline, &mut args)?),
+            "--skip-unchanged" => {
+                parsed.skip_unchanged = Some(SkipMode::parse(&take_value(&name, &mut inline, &mut args)?)?)
+            }
+            "-j" | "--jobs" => {
+                let value = take_value(&name, &mut inline, &mut args)?;
+                match value.parse::<usize>() {
+                    Ok(jobs) if jobs > 0 => parsed.jobs = Some(jobs),
+                    _ => return Err(format!("{} expects a positive number, not '{}'", name, value)),
+                }
+            }
+            "--check" => parsed.check = true,
+            "--diff" => parsed.diff = true,
+            "--interval" => {
+                let value = take_value(&name, &mut inline, &mut args)?;
+                match value.parse::<u64>() {
+                    Ok(ms) if ms > 0 => parsed.interval = Some(ms),
+                    _ => return Err(format!("{} expects a positive number, not '{}'", name, value)),
+                }
+            }
+            "--" => {
+                parsed.inputs.extend(args.by_ref().map(PathBuf::from));
+                break;
+            }
+            "-" => parsed.inputs.push(PathBuf::from("-")),
+            _ if name.starts_with("--") => {
+                let key = name[2..].replace('-', "_");
+                let negated = key.strip_prefix("no_").filter(|k| fields.get(*k).is_some_and(Value::is_boolean));
+                let override_ = match (negated, fields.get(&key)) {
+                    (Some(key), _) if inline.is_none() => Override {
+                        key: key.to_string(),
+                        value: Value::Bool(false),
+                    },
+                    (_, Some(Value::Bool(_))) => {
+                        let value = match inline.take().as_deref() {
+                            None | Some("true") => true,
+                            Some("false") => false,
+                            Some(other) => return Err(format!("{} expects true or false, not '{}'", name, other)),
+                        };
+                        Override {
+                            key,
+                            value: Value::Bool(value),
+                        }
+                    }
+                    (_, Some(default)) => {
+                        let default = default.clone();
+                        let value = field_value(&name, &default, take_value(&name, &mut inline, &mut args)?)?;
+                        Override { key, value }
+                    }
+                    _ => return Err(format!("unknown option '{}'", name)),
+                };
+                parsed.overrides.push(override_);
+            }
+            _ if name.starts_with('-') && name.len() > 1 => return Err(format!("unknown option '{}'", name)),
+            _ => parsed.inputs.push(PathBuf::from(arg)),
+        }
+    }
+
+    let converting = watch || parsed.output.is_some() || parsed.to != Format::Markdown;
+    if (parsed.check || parsed.diff) && converting {
+        return Err("--check and --diff only compare inputs with their Markdown output".to_string());
+    }
+    if watch {
+        Ok(Command::Watch(parsed))
+    } else if parsed.interval.is_some() {
+        Err("--interval only applies to 'tform watch'".to_string())
+    } else {
+        Ok(Command::Run(parsed))
+    }
+}
+
+/// Takes an option's value, from "--name=value" or else the next argument.
+fn take_value(
+    name: &str,
+    inline: &mut Option<String>,
+    args: &mut impl Iterator<Item = String>,
+) -> Result<String, String> {
+    inline
+        .take()
+        .or_else(|| args.next())
+        .ok_or_else(|| format!("{} expects a value", name))
+}
+
+/// Converts a command-line value to the JSON type of the field it sets.
+fn field_value(name: &str, default: &Value, value: String) -> Result<Value, String> {
+    match default {
+        Value::Number(_) => value
+            .parse::<u64>()
+            .map(Value::from)
+            .map_err(|_| format!("{} expects a number, not '{}'", name, value)),
+        // Lists grow by one entry per use; entries may be JSON objects (e.g. a full custom rule)
+        Value::Array(_) if value.trim_start().starts_with('{') => {
+            serde_json::from_str(&value).map_err(|e| format!("{}: invalid JSON: {}", name, e))
+        }
+        _ => Ok(Value::String(value)),
+    }
+}
+
+/// Applies command-line overrides on top of a loaded `Config`.
+pub fn apply_overrides(config: Config, overrides: &[Override]) -> Result<Config, String> {
+    let mut value = serde_json::to_value(config).map_err(|e| e.to_string())?;
+    let fields = value.as_object_mut().ok_or("config is not an object")?;
+    for Override { key, value } in overrides {
+        match fields.get_mut(key) {
+            Some(Value::Array(list)) => list.push(value.clone()),
+            _ => {
+                fields.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    serde_json::from_value(value).map_err(|e| format!("invalid option: {}", e))
+}
+
+/// The `Config` fields and their default values.
+fn config_fields() -> Map<String, Value> {
+    match serde_json::to_value(Config::default()) {
+        Ok(Value::Object(fields)) => fields,
+        _ => Map::new(),
+    }
+}
+
+/// The `--help` text, listing every `Config` override with its default.
+pub fn usage() -> String {
+    let mut text = String::from(
+        "Usage: tform [OPTIONS] [FILE...]
+       tform [OPTIONS] DIR -o OUTDIR
+       tform watch [OPTIONS] DIR -o OUTDIR
+
+Formats plain text as Markdown, HTML or JSON. Reads the FILEs in order as one
+document, or stdin when none are given (or for '-').
+
+Given a directory, converts every matching file in it (recursively) into the
+same tree under OUTDIR, with the extension of the output format. 'tform watch'
+does the same, then keeps polling DIR and reconverts files as they change,
+removing the outputs of deleted files. It also reloads the --config file when
+that changes, and reconverts everything. No config file is read or watched
+without --config, not even tform_config.toml. Stop it with Ctrl-C.
+
+Options:
+  -t, --to FORMAT        Output format: markdown (default), html or json
+  -o, --output PATH      Write to PATH instead of stdout (not one of the inputs)
+  -c, --config PATH      Load a TOML or JSON config file (none by default)
+  --check                Don't convert; list the inputs whose Markdown output
+                         differs from them, and exit with 1 if there are any
+  --diff                 Don't convert; print a unified diff from each input
+                         to its Markdown output (with --check, also exit with 1)
+  -h, --help             Print this help
+  -V, --version          Print the version
+
+Directory options:
+  --include GLOB         Convert files matching GLOB (repeatable; default '*.txt')
+  --exclude GLOB         Skip files and directories matching GLOB (repeatable)
+  --skip-unchanged MODE  Skip files unchanged since the last run: 'mtime'
+                         (output newer than input) or 'hash' (same content
+                         and config, recorded in OUTDIR/.tform-cache)
+  -j, --jobs N           Convert N files at a time (default: one per CPU)
+  --interval MS          'tform watch': check for changes every MS milliseconds
+                         (default 500)
+
+Config overrides (applied after --config):
+",
+    );
+    for (key, default) in config_fields() {
+        let flag = key.replace('_', "-");
+        let line = match default {
+            Value::Bool(default) => format!("--{flag}, --no-{flag}  (default: {default})"),
+            Value::Number(default) => format!("--{flag} N  (default: {default})"),
+            Value::Array(_) => format!("--{flag} VALUE  (repeatable)"),
+            _ => format!("--{flag} VALUE"),
+        };
+        text.push_str(&format!("  {}\n", line));
+    }
+    text.push_str(
+        "
+Exit codes:
+  0  success
+  1  --check: some input is not formatted
+  2  invalid command line
+  3  I/O error (reading input, writing output)
+  4  parse error (e.g. invalid config file or pattern)
+In directory mode, a failed file sets the exit code but does not stop the run.
+",
+    );
+    text
+}
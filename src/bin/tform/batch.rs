@@ -0,0 +1,347 @@
+/*!
+Directory mode: converts every matching file under a directory into a mirrored
+tree, several files at a time.
+
+With `--skip-unchanged hash`, the content hash of each converted file is kept in
+`OUTDIR/.tform-cache`, one `hash<TAB>path` line per file. The hash also covers
+the config and output format, so changing either converts everything again.
+*/
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use tform::errors::FormatterError;
+use tform::Formatter;
+
+use crate::args::{Format, SkipMode};
+
+/// Name of the hash cache in the output directory.
+const CACHE_FILE: &str = ".tform-cache";
+
+/// Files converted when no `--include` is given.
+const DEFAULT_INCLUDE: &str = "*.txt";
+
+/// A directory conversion: which files to convert, and how.
+pub struct Batch {
+    pub formatter: Formatter,
+    pub format: Format,
+    pub src: PathBuf,
+    pub dest: PathBuf,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    /// Hash of the config and output format, folded into every file's hash.
+    pub fingerprint: u64,
+}
+
+/// What happened to the files of a run.
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub converted: usize,
+    pub skipped: usize,
+    /// Failed files (relative to the input directory) with their errors, in path order.
+    pub failed: Vec<(PathBuf, FormatterError)>,
+}
+
+/// What happened to one file, with its content hash when it was computed.
+enum Outcome {
+    Converted(Option<u64>),
+    Skipped(Option<u64>),
+    Failed(FormatterError),
+}
+
+impl Batch {
+    /// The files to convert, relative to `src`, in path order.
+    pub fn files(&self) -> io::Result<Vec<PathBuf>> {
+        // Don't convert our own output when it lives inside the input directory
+        let dest = fs::canonicalize(&self.dest).ok();
+        let mut files = vec![];
+        let mut dirs = vec![PathBuf::new()];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(self.src.join(&dir))? {
+                let entry = entry?;
+                let rel = dir.join(entry.file_name());
+                let name = slash_path(&rel);
+                if self.exclude.iter().any(|glob| glob_match(glob, &name)) {
+                    continue;
+                }
+                if entry.file_type()?.is_dir() {
+                    if dest.is_none() || fs::canonicalize(entry.path()).ok() != dest {
+                        dirs.push(rel);
+                    }
+                } else if self.included(&name) {
+                    files.push(rel);
+                }
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    fn included(&self, name: &str) -> bool {
+        if self.include.is_empty() {
+            glob_match(DEFAULT_INCLUDE, name)
+        } else {
+            self.include.iter().any(|glob| glob_match(glob, name))
+        }
+    }
+
+    /// Where the file at `rel` (relative to `src`) is written.
+    ///
+    /// Inputs that differ only in their extension, like `a.txt` and `a.md`,
+    /// get the same output path; see `collisions`.
+    pub fn output_path(&self, rel: &Path) -> PathBuf {
+        let extension = match self.format {
+            Format::Markdown => "md",
+            Format::Html => "html",
+            Format::Json => "json",
+        };
+        self.dest.join(rel).with_extension(extension)
+    }
+
+    /// The files among `files` whose output path another one has too, with
+    /// that other file. Converting them would overwrite one with the other, so
+    /// none of them is converted.
+    fn collisions<'a>(&self, files: &'a [PathBuf]) -> HashMap<&'a PathBuf, &'a PathBuf> {
+        let mut by_output: HashMap<PathBuf, Vec<&PathBuf>> = HashMap::new();
+        for rel in files {
+            by_output.entry(self.output_path(rel)).or_default().push(rel);
+        }
+        let mut collisions = HashMap::new();
+        for group in by_output.values().filter(|group| group.len() > 1) {
+            for (i, rel) in group.iter().enumerate() {
+                collisions.insert(*rel, group[if i == 0 { 1 } else { 0 }]);
+            }
+        }
+        collisions
+    }
+
+    /// Converts one file, creating its output directory as needed.
+    pub fn convert(&self, rel: &Path) -> Result<(), FormatterError> {
+        let input = File::open(self.src.join(rel))?;
+        self.convert_from(rel, input)
+    }
+
+    fn convert_from(&self, rel: &Path, input: impl Read) -> Result<(), FormatterError> {
+        let path = self.output_path(rel);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(FormatterError::WriteError)?;
+        }
+        let mut output = BufWriter::new(File::create(&path).map_err(FormatterError::WriteError)?);
+        let result = match self.format {
+            Format::Markdown => self.formatter.format_markdown_to(input, output),
+            Format::Html => self.formatter.format_html_to(input, output),
+            Format::Json => self
+                .formatter
+                .format_json_to(input, &mut output)
+                .and_then(|()| crate::end_json(output)),
+        };
+        if result.is_err() {
+            // Don't leave a truncated file behind, or mtime mode would skip it next time
+            fs::remove_file(&path).ok();
+        }
+        result
+    }
+
+    /// Converts all files on `jobs` threads, skipping unchanged ones if asked to.
+    pub fn run(&self, skip: Option<SkipMode>, jobs: usize) -> io::Result<Summary> {
+        let files = self.files()?;
+        self.convert_files(&files, &files, skip, jobs)
+    }
+
+    /// Converts the given files (relative to `src`) on `jobs` threads, skipping
+    /// unchanged ones if asked to. `all` are all the files of the directory:
+    /// outputs mustn't collide with theirs, and the hash cache keeps their entries.
+    pub fn convert_files(
+        &self,
+        files: &[PathBuf],
+        all: &[PathBuf],
+        skip: Option<SkipMode>,
+        jobs: usize,
+    ) -> io::Result<Summary> {
+        let mut cache = match skip {
+            Some(SkipMode::Hash) => self.read_cache(),
+            _ => HashMap::new(),
+        };
+        let (summary, hashes) = self.process_all(files, all, skip, &cache, jobs);
+        if skip == Some(SkipMode::Hash) {
+            // Failed files lose their entry, and files no longer there are forgotten
+            for rel in files {
+                cache.remove(&slash_path(rel));
+            }
+            cache.extend(hashes.into_iter().map(|(rel, hash)| (slash_path(rel), hash)));
+            let all: HashSet<String> = all.iter().map(|rel| slash_path(rel)).collect();
+            cache.retain(|path, _| all.contains(path));
+            self.write_cache(&cache)?;
+        }
+        Ok(summary)
+    }
+
+    /// Processes `files` in parallel, returning the summary and the content hashes computed.
+    fn process_all<'a>(
+        &self,
+        files: &'a [PathBuf],
+        all: &[PathBuf],
+        skip: Option<SkipMode>,
+        cache: &HashMap<String, u64>,
+        jobs: usize,
+    ) -> (Summary, Vec<(&'a PathBuf, u64)>) {
+        let collisions = self.collisions(all);
+        let next = AtomicUsize::new(0);
+        let outcomes = Mutex::new(Vec::with_capacity(files.len()));
+        std::thread::scope(|scope| {
+            for _ in 0..jobs.clamp(1, files.len().max(1)) {
+                scope.spawn(|| {
+                    while let Some(rel) = files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        let outcome = match collisions.get(rel) {
+                            Some(other) => Outcome::Failed(self.collision_error(rel, other)),
+                            None => self.process(rel, skip, cache),
+                        };
+                        outcomes.lock().unwrap_or_else(|e| e.into_inner()).push((rel, outcome));
+                    }
+                });
+            }
+        });
+        let mut outcomes = outcomes.into_inner().unwrap_or_else(|e| e.into_inner());
+        outcomes.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut summary = Summary::default();
+        let mut hashes = vec![];
+        for (rel, outcome) in outcomes {
+            if let Outcome::Converted(Some(hash)) | Outcome::Skipped(Some(hash)) = outcome {
+                hashes.push((rel, hash));
+            }
+            match outcome {
+                Outcome::Converted(_) => summary.converted += 1,
+                Outcome::Skipped(_) => summary.skipped += 1,
+                Outcome::Failed(error) => summary.failed.push((rel.clone(), error)),
+            }
+        }
+        (summary, hashes)
+    }
+
+    fn collision_error(&self, rel: &Path, other: &Path) -> FormatterError {
+        let message = format!(
+            "{} would also be written from {}",
+            self.output_path(rel).display(),
+            self.src.join(other).display()
+        );
+        FormatterError::WriteError(io::Error::new(io::ErrorKind::AlreadyExists, message))
+    }
+
+    /// Converts one file unless `skip` says it is unchanged.
+    fn process(&self, rel: &Path, skip: Option<SkipMode>, cache: &HashMap<String, u64>) -> Outcome {
+        let result = match skip {
+            None => self.convert(rel).map(|()| Outcome::Converted(None)),
+            Some(SkipMode::Mtime) => self.is_newer(rel).and_then(|newer| {
+                if newer {
+                    Ok(Outcome::Skipped(None))
+                } else {
+                    self.convert(rel).map(|()| Outcome::Converted(None))
+                }
+            }),
+            Some(SkipMode::Hash) => fs::read(self.src.join(rel))
+                .map_err(FormatterError::IoError)
+                .and_then(|content| {
+                    let hash = fnv1a(self.fingerprint, &content);
+                    if cache.get(&slash_path(rel)) == Some(&hash) && self.output_path(rel).exists() {
+                        Ok(Outcome::Skipped(Some(hash)))
+                    } else {
+                        self.convert_from(rel, content.as_slice()).map(|()| Outcome::Converted(Some(hash)))
+                    }
+                }),
+        };
+        result.unwrap_or_else(Outcome::Failed)
+    }
+
+    /// Whether the output of `rel` exists and is at least as new as its input.
+    fn is_newer(&self, rel: &Path) -> Result<bool, FormatterError> {
+        let input = fs::metadata(self.src.join(rel))?.modified()?;
+        Ok(match fs::metadata(self.output_path(rel)) {
+            Ok(output) => output.modified()? >= input,
+            Err(_) => false,
+        })
+    }
+
+    /// Reads the hash cache; a missing or damaged cache is treated as empty.
+    fn read_cache(&self) -> HashMap<String, u64> {
+        let content = fs::read_to_string(self.dest.join(CACHE_FILE)).unwrap_or_default();
+        content
+            .lines()
+            .filter_map(|line| {
+                let (hash, path) = line.split_once('\t')?;
+                Some((path.to_string(), u64::from_str_radix(hash, 16).ok()?))
+            })
+            .collect()
+    }
+
+    fn write_cache(&self, cache: &HashMap<String, u64>) -> io::Result<()> {
+        let mut entries: Vec<_> = cache.iter().collect();
+        entries.sort();
+        let mut content = String::new();
+        for (path, hash) in entries {
+            content.push_str(&format!("{:016x}\t{}\n", hash, path));
+        }
+        fs::create_dir_all(&self.dest)?;
+        fs::write(self.dest.join(CACHE_FILE), content)
+    }
+}
+
+/// A relative path with '/' separators, as globs and the cache see it.
+fn slash_path(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Matches a relative, '/'-separated path against a glob.
+///
+/// `*` and `?` match within one path component and `**` matches any number of
+/// components. A glob without '/' is matched against the last component only,
+/// so `*.txt` matches at any depth and `drafts` excludes every `drafts` directory.
+pub fn glob_match(glob: &str, path: &str) -> bool {
+    if !glob.contains('/') {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        return wildcard_match(&glob.chars().collect::<Vec<_>>(), &name.chars().collect::<Vec<_>>());
+    }
+    let glob: Vec<&str> = glob.split('/').collect();
+    let path: Vec<&str> = path.split('/').collect();
+    components_match(&glob, &path)
+}
+
+fn components_match(glob: &[&str], path: &[&str]) -> bool {
+    match glob.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| components_match(rest, &path[skip..])),
+        Some((first, rest)) => path.split_first().is_some_and(|(name, path)| {
+            wildcard_match(&first.chars().collect::<Vec<_>>(), &name.chars().collect::<Vec<_>>())
+                && components_match(rest, path)
+        }),
+    }
+}
+
+fn wildcard_match(glob: &[char], name: &[char]) -> bool {
+    match glob.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| wildcard_match(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && wildcard_match(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && wildcard_match(rest, &name[1..]),
+    }
+}
+
+/// Starting value of `fnv1a`.
+pub const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// FNV-1a, continuing from `hash`. Used instead of `DefaultHasher` because the
+/// cache must hash the same way across Rust versions.
+pub fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
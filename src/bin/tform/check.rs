@@ -0,0 +1,64 @@
+/*!
+`--check` and `--diff`: compare each input with its Markdown output, without
+writing any files.
+
+The Markdown output always ends with a blank line, while editors usually save a
+single final newline, so an input ending either way counts as formatted; diffs
+use the single newline.
+*/
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+
+use tform::errors::FormatterError;
+use tform::Formatter;
+
+use crate::args::Args;
+use crate::diff;
+
+/// Compares every input (stdin if there are none) with its Markdown output,
+/// printing the name or the diff of each one that differs. Returns whether any did.
+pub fn check(args: &Args) -> Result<bool, FormatterError> {
+    let formatter = Formatter::try_new(crate::load_config(args)?)?;
+    let stdin = [PathBuf::from("-")];
+    let inputs = if args.inputs.is_empty() { &stdin[..] } else { &args.inputs[..] };
+
+    let mut changed = false;
+    let mut stdout = io::stdout().lock();
+    for path in inputs {
+        let (name, input) = if path.as_os_str() == "-" {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            ("<stdin>".to_string(), input)
+        } else {
+            let input = fs::read_to_string(path).map_err(|e| crate::with_path(e, path))?;
+            (path.display().to_string(), input)
+        };
+        let output = formatter.format_to_markdown(input.as_bytes())?;
+
+        let expected = with_final_newline(&output);
+        if input == expected || input == output {
+            continue;
+        }
+        changed = true;
+        let report = if args.diff {
+            diff::unified_diff(&input, &expected, &name, &name)
+        } else {
+            format!("Would reformat: {}\n", name)
+        };
+        stdout.write_all(report.as_bytes()).map_err(FormatterError::WriteError)?;
+    }
+    stdout.flush().map_err(FormatterError::WriteError)?;
+    Ok(changed)
+}
+
+/// `text` with its trailing newlines replaced by a single one (none if it is empty).
+fn with_final_newline(text: &str) -> String {
+    let text = text.trim_end_matches('\n');
+    if text.is_empty() {
+        String::new()
+    } else {
+        format!("{}\n", text)
+    }
+}
@@ -0,0 +1,257 @@
+/*!
+Line diffs in unified format, for `--diff`.
+
+Uses Myers' algorithm in linear space: each step finds the middle of the
+shortest edit path and recurses on both halves, so memory grows with the size
+of the input. A half whose search passes `MAX_COST` edits is written as deleted
+and reinserted whole, which bounds the time on inputs that barely match.
+*/
+
+use std::ops::Range;
+
+/// Lines of unchanged context around each hunk.
+const CONTEXT: usize = 3;
+
+/// How many edits the middle-snake search explores from each end before giving up.
+const MAX_COST: usize = 1024;
+
+/// One step from the old text to the new, by line index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// A unified diff from `old` to `new`, or an empty string if they are equal.
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let old: Vec<&str> = old.split_inclusive('\n').collect();
+    let new: Vec<&str> = new.split_inclusive('\n').collect();
+    let edits = diff_lines(&old, &new);
+    if edits.iter().all(|edit| matches!(edit, Edit::Equal(..))) {
+        return String::new();
+    }
+
+    let mut output = format!("--- {}\n+++ {}\n", old_name, new_name);
+    for hunk in hunks(&edits) {
+        let before = &edits[..hunk.start];
+        let hunk = &edits[hunk];
+        let (mut old_start, mut old_count, mut new_start, mut new_count) = (None, 0, None, 0);
+        for edit in hunk {
+            match *edit {
+                Edit::Equal(i, j) => {
+                    old_start.get_or_insert(i);
+                    new_start.get_or_insert(j);
+                    old_count += 1;
+                    new_count += 1;
+                }
+                Edit::Delete(i) => {
+                    old_start.get_or_insert(i);
+                    old_count += 1;
+                }
+                Edit::Insert(j) => {
+                    new_start.get_or_insert(j);
+                    new_count += 1;
+                }
+            }
+        }
+        // An empty side is numbered by the line before it, as in diff(1)
+        let old_start = old_start.map_or_else(|| line_count(before, true), |i| i + 1);
+        let new_start = new_start.map_or_else(|| line_count(before, false), |j| j + 1);
+        output.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(old_start, old_count),
+            range(new_start, new_count)
+        ));
+
+        for edit in hunk {
+            let (prefix, line) = match *edit {
+                Edit::Equal(i, _) => (' ', old[i]),
+                Edit::Delete(i) => ('-', old[i]),
+                Edit::Insert(j) => ('+', new[j]),
+            };
+            output.push(prefix);
+            output.push_str(line);
+            if !line.ends_with('\n') {
+                output.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    output
+}
+
+/// Formats a hunk range, leaving out a count of 1.
+fn range(start: usize, count: usize) -> String {
+    if count == 1 {
+        start.to_string()
+    } else {
+        format!("{},{}", start, count)
+    }
+}
+
+/// The number of old (or new) lines the edits cover.
+fn line_count(edits: &[Edit], old: bool) -> usize {
+    edits
+        .iter()
+        .filter(|edit| match edit {
+            Edit::Equal(..) => true,
+            Edit::Delete(_) => old,
+            Edit::Insert(_) => !old,
+        })
+        .count()
+}
+
+/// Splits the edits into hunks: runs of changes with up to `CONTEXT` equal lines around them.
+fn hunks(edits: &[Edit]) -> Vec<Range<usize>> {
+    let changes: Vec<usize> = (0..edits.len())
+        .filter(|&i| !matches!(edits[i], Edit::Equal(..)))
+        .collect();
+    let mut hunks = vec![];
+    let mut i = 0;
+    while i < changes.len() {
+        let start = changes[i].saturating_sub(CONTEXT);
+        // Merge changes whose contexts touch or overlap
+        while i + 1 < changes.len() && changes[i + 1] - changes[i] <= 2 * CONTEXT + 1 {
+            i += 1;
+        }
+        let end = (changes[i] + CONTEXT + 1).min(edits.len());
+        hunks.push(start..end);
+        i += 1;
+    }
+    hunks
+}
+
+/// The shortest edit script from `old` to `new` (Myers, 1986).
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    let mut edits = vec![];
+    diff_range(old, new, 0..old.len(), 0..new.len(), &mut edits);
+    // Within each run of changes, deletions come first, as in diff(1)
+    for run in edits.split_mut(|edit| matches!(edit, Edit::Equal(..))) {
+        run.sort_by_key(|edit| matches!(edit, Edit::Insert(_)));
+    }
+    edits
+}
+
+/// Appends the edits from `old[old_range]` to `new[new_range]`.
+fn diff_range(
+    old: &[&str],
+    new: &[&str],
+    mut old_range: Range<usize>,
+    mut new_range: Range<usize>,
+    edits: &mut Vec<Edit>,
+) {
+    // Common prefix and suffix lines are equal whatever the rest is
+    while !old_range.is_empty()
+        && !new_range.is_empty()
+        && old[old_range.start] == new[new_range.start]
+    {
+        edits.push(Edit::Equal(old_range.start, new_range.start));
+        old_range.start += 1;
+        new_range.start += 1;
+    }
+    let mut suffix = 0;
+    while old_range.len() > suffix
+        && new_range.len() > suffix
+        && old[old_range.end - suffix - 1] == new[new_range.end - suffix - 1]
+    {
+        suffix += 1;
+    }
+    old_range.end -= suffix;
+    new_range.end -= suffix;
+
+    if old_range.is_empty() || new_range.is_empty() {
+        edits.extend(old_range.clone().map(Edit::Delete));
+        edits.extend(new_range.clone().map(Edit::Insert));
+    } else if let Some((x, y)) = middle_snake(old, new, old_range.clone(), new_range.clone()) {
+        diff_range(old, new, old_range.start..x, new_range.start..y, edits);
+        diff_range(old, new, x..old_range.end, y..new_range.end, edits);
+    } else {
+        edits.extend(old_range.clone().map(Edit::Delete));
+        edits.extend(new_range.clone().map(Edit::Insert));
+    }
+
+    edits.extend((0..suffix).map(|i| Edit::Equal(old_range.end + i, new_range.end + i)));
+}
+
+/// Furthest-reaching x per diagonal k = x - y, for k in `-max..=max`.
+struct Diagonals {
+    x: Vec<usize>,
+    max: isize,
+}
+
+impl Diagonals {
+    fn new(max: usize) -> Self {
+        Diagonals {
+            x: vec![0; 2 * max + 3],
+            max: max as isize + 1,
+        }
+    }
+
+    fn get(&self, k: isize) -> usize {
+        self.x[(k + self.max) as usize]
+    }
+
+    fn set(&mut self, k: isize, x: usize) {
+        self.x[(k + self.max) as usize] = x;
+    }
+}
+
+/// A point on a shortest edit path between the two ranges, other than their
+/// start and end, found by searching from both ends until the paths meet.
+///
+/// The ranges must differ in their first and last lines. Returns `None` if the
+/// paths don't meet within `MAX_COST` edits from each end.
+fn middle_snake(
+    old: &[&str],
+    new: &[&str],
+    old_range: Range<usize>,
+    new_range: Range<usize>,
+) -> Option<(usize, usize)> {
+    let (n, m) = (old_range.len(), new_range.len());
+    let (old, new) = (&old[old_range.clone()], &new[new_range.clone()]);
+    let delta = n as isize - m as isize;
+    let odd = delta & 1 == 1;
+    let max = (n + m).div_ceil(2).min(MAX_COST);
+    // Forward paths count x from the start, backward paths from the end
+    let mut forward = Diagonals::new(max);
+    let mut backward = Diagonals::new(max);
+
+    for d in 0..=max as isize {
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && forward.get(k - 1) < forward.get(k + 1)) {
+                forward.get(k + 1)
+            } else {
+                forward.get(k - 1) + 1
+            };
+            let (x0, y0) = (x, (x as isize - k) as usize);
+            let mut y = y0;
+            while x < n && y < m && old[x] == new[y] {
+                x += 1;
+                y += 1;
+            }
+            forward.set(k, x);
+            // With an odd delta the paths meet after a forward round
+            if odd && (k - delta).abs() < d && x + backward.get(delta - k) >= n {
+                return Some((old_range.start + x0, new_range.start + y0));
+            }
+        }
+
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && backward.get(k - 1) < backward.get(k + 1)) {
+                backward.get(k + 1)
+            } else {
+                backward.get(k - 1) + 1
+            };
+            let mut y = (x as isize - k) as usize;
+            while x < n && y < m && old[n - x - 1] == new[m - y - 1] {
+                x += 1;
+                y += 1;
+            }
+            backward.set(k, x);
+            if !odd && (k - delta).abs() <= d && x + forward.get(delta - k) >= n {
+                return Some((old_range.start + n - x, new_range.start + m - y));
+            }
+        }
+    }
+    None
+}
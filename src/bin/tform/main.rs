@@ -0,0 +1,267 @@
+/*!
+`tform` command-line tool: formats text files (or stdin) as Markdown, HTML or JSON,
+or a whole directory of them into another directory.
+
+Run `tform --help` for the options.
+*/
+
+mod args;
+mod batch;
+mod check;
+mod diff;
+mod watch;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+use args::{Args, Command, Format};
+use batch::Batch;
+use tform::errors::FormatterError;
+use tform::{Config, Formatter};
+
+/// Exit code for `--check` when some input is not formatted.
+const EXIT_CHANGED: u8 = 1;
+/// Exit code for an invalid command line.
+const EXIT_USAGE: u8 = 2;
+/// Exit code for I/O errors (`FormatterError::IoError` and `WriteError`).
+const EXIT_IO: u8 = 3;
+/// Exit code for parse errors (`FormatterError::ParseError`).
+const EXIT_PARSE: u8 = 4;
+
+fn main() -> ExitCode {
+    let command = match args::parse(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(message) => return usage_error(&message),
+    };
+
+    let result = match command {
+        Command::Help => {
+            print!("{}", args::usage());
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Version => {
+            println!("tform {}", env!("CARGO_PKG_VERSION"));
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Run(args) if args.check || args.diff => {
+            if args.inputs.iter().any(|path| path.is_dir()) || args.has_batch_options() {
+                return usage_error("--check and --diff take files, not directories");
+            }
+            check::check(&args).map(|changed| match changed && args.check {
+                true => ExitCode::from(EXIT_CHANGED),
+                false => ExitCode::SUCCESS,
+            })
+        }
+        Command::Run(args) => match batch_dir(&args) {
+            Ok(Some(dir)) => run_batch(&args, dir),
+            Ok(None) => match overwritten_input(&args) {
+                Some(path) => return usage_error(&format!("the output file {} is also an input", path.display())),
+                None => run(&args).map(|()| ExitCode::SUCCESS),
+            },
+            Err(message) => return usage_error(&message),
+        },
+        Command::Watch(args) => match batch_dir(&args) {
+            Ok(Some(dir)) => watch::watch(&args, dir).map(|()| ExitCode::SUCCESS),
+            Ok(None) => return usage_error("watch needs a directory to watch"),
+            Err(message) => return usage_error(&message),
+        },
+    };
+
+    match result {
+        Ok(code) => code,
+        // The reader went away (e.g. `tform ... | head`): nothing left to do
+        Err(FormatterError::WriteError(e)) if e.kind() == io::ErrorKind::BrokenPipe => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("tform: {}", e);
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+fn usage_error(message: &str) -> ExitCode {
+    eprintln!("tform: {}\nTry 'tform --help' for more information.", message);
+    ExitCode::from(EXIT_USAGE)
+}
+
+/// Maps an error to the process exit code.
+fn exit_code(error: &FormatterError) -> u8 {
+    match error {
+        FormatterError::IoError(_) | FormatterError::WriteError(_) => EXIT_IO,
+        FormatterError::ParseError(_) => EXIT_PARSE,
+    }
+}
+
+/// Converts the inputs to the requested format.
+fn run(args: &Args) -> Result<(), FormatterError> {
+    let formatter = Formatter::try_new(load_config(args)?)?;
+    let input = open_inputs(args)?;
+
+    let output: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(File::create(path).map_err(|e| with_path(e, path))?),
+        None => Box::new(io::stdout().lock()),
+    };
+    let mut output = BufWriter::new(output);
+
+    match args.to {
+        Format::Markdown => formatter.format_markdown_to(input, output),
+        Format::Html => formatter.format_html_to(input, output),
+        Format::Json => formatter
+            .format_json_to(input, &mut output)
+            .and_then(|()| end_json(output)),
+    }
+}
+
+/// Ends JSON output with a newline, as the Markdown and HTML output end.
+fn end_json(mut output: impl Write) -> Result<(), FormatterError> {
+    output
+        .write_all(b"\n")
+        .and_then(|()| output.flush())
+        .map_err(FormatterError::WriteError)
+}
+
+/// The input directory in directory mode, after checking the options fit it.
+fn batch_dir(args: &Args) -> Result<Option<&Path>, String> {
+    if !args.inputs.iter().any(|path| path.is_dir()) {
+        if args.has_batch_options() {
+            return Err("--include, --exclude, --skip-unchanged and --jobs need a directory input".to_string());
+        }
+        return Ok(None);
+    }
+    let [dir] = args.inputs.as_slice() else {
+        return Err("a directory must be the only input".to_string());
+    };
+    let Some(dest) = &args.output else {
+        return Err("converting a directory needs an output directory (-o OUTDIR)".to_string());
+    };
+    if dest.is_file() {
+        return Err(format!("{} is not a directory", dest.display()));
+    }
+    if std::fs::canonicalize(dir).ok() == std::fs::canonicalize(dest).ok() {
+        return Err("the output directory must differ from the input directory".to_string());
+    }
+    Ok(Some(dir))
+}
+
+/// The input that `-o` names too, which creating the output would empty before
+/// it is read.
+fn overwritten_input(args: &Args) -> Option<&Path> {
+    let output = std::fs::canonicalize(args.output.as_ref()?).ok()?;
+    args.inputs
+        .iter()
+        .find(|path| std::fs::canonicalize(path).is_ok_and(|path| path == output))
+        .map(|path| path.as_path())
+}
+
+/// Converts every matching file under `dir`, printing a summary.
+fn run_batch(args: &Args, dir: &Path) -> Result<ExitCode, FormatterError> {
+    let batch = new_batch(args, dir)?;
+    let summary = batch.run(args.skip_unchanged, jobs(args)).map_err(|e| with_path(e, dir))?;
+    print_summary(dir, &summary);
+    Ok(match summary.failed.first() {
+        Some((_, error)) => ExitCode::from(exit_code(error)),
+        None => ExitCode::SUCCESS,
+    })
+}
+
+/// Sets up the conversion of `dir` with the config and options in `args`.
+fn new_batch(args: &Args, dir: &Path) -> Result<Batch, FormatterError> {
+    let config = load_config(args)?;
+    // Any change to the config or output format invalidates the hash cache
+    let fingerprint = format!("{} {:?} {}", env!("CARGO_PKG_VERSION"), args.to, serde_json::to_string(&config)?);
+    Ok(Batch {
+        formatter: Formatter::try_new(config)?,
+        format: args.to,
+        src: dir.to_path_buf(),
+        dest: args.output.clone().unwrap_or_default(),
+        include: args.include.clone(),
+        exclude: args.exclude.clone(),
+        fingerprint: batch::fnv1a(batch::FNV_OFFSET, fingerprint.as_bytes()),
+    })
+}
+
+/// Number of files to convert at a time: `--jobs`, or one per CPU.
+fn jobs(args: &Args) -> usize {
+    args.jobs
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()))
+}
+
+/// Reports the failed files on stderr and the totals on stdout.
+fn print_summary(dir: &Path, summary: &batch::Summary) {
+    for (rel, error) in &summary.failed {
+        eprintln!("tform: {}: {}", dir.join(rel).display(), error);
+    }
+    println!(
+        "{} converted, {} skipped, {} failed",
+        summary.converted,
+        summary.skipped,
+        summary.failed.len()
+    );
+}
+
+/// Loads `--config` (or the defaults) and applies the command-line overrides.
+fn load_config(args: &Args) -> Result<Config, FormatterError> {
+    let config = match &args.config {
+        Some(path) => Config::from_file(path).map_err(|e| match e {
+            FormatterError::IoError(e) => FormatterError::IoError(with_path(e, path)),
+            FormatterError::ParseError(e) => FormatterError::ParseError(format!("{}: {}", path.display(), e)),
+            e => e,
+        })?,
+        None => Config::default(),
+    };
+    args::apply_overrides(config, &args.overrides).map_err(FormatterError::ParseError)
+}
+
+/// Opens the input files as one stream, in order; stdin if there are none.
+///
+/// Each input is ended with a newline, so the last line of one file never
+/// runs into the first line of the next.
+fn open_inputs(args: &Args) -> Result<Box<dyn Read>, FormatterError> {
+    if args.inputs.is_empty() {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+    let mut input: Box<dyn Read> = Box::new(io::empty());
+    for path in &args.inputs {
+        let next: Box<dyn Read> = if path.as_os_str() == "-" {
+            Box::new(io::stdin().lock())
+        } else {
+            Box::new(File::open(path).map_err(|e| with_path(e, path))?)
+        };
+        input = Box::new(input.chain(EndWithNewline { inner: next, last: None }));
+    }
+    Ok(input)
+}
+
+/// A reader that adds a `\n` after its input if it doesn't already end with one.
+struct EndWithNewline<R> {
+    inner: R,
+    /// The last byte read, or `None` before the first read.
+    last: Option<u8>,
+}
+
+impl<R: Read> Read for EndWithNewline<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.last = Some(buf[n - 1]);
+            return Ok(n);
+        }
+        match self.last {
+            Some(b'\n') | None => Ok(0),
+            Some(_) => {
+                buf[0] = b'\n';
+                self.last = Some(b'\n');
+                Ok(1)
+            }
+        }
+    }
+}
+
+/// Adds the path to an I/O error message.
+fn with_path(error: io::Error, path: &Path) -> io::Error {
+    io::Error::new(error.kind(), format!("{}: {}", path.display(), error))
+}
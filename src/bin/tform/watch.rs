@@ -0,0 +1,138 @@
+/*!
+Watch mode: converts a directory like directory mode, then polls it and
+reconverts each file whose modification time or size changes, and removes the
+output of each file that is deleted. Only a config file given with `--config`
+is polled (`tform_config.toml` is not read unless named); when it changes, the
+`Config` is reloaded and every file is converted again.
+
+Polling keeps this free of platform APIs and works on any local or network
+filesystem, at the cost of noticing a change up to one interval late.
+*/
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use tform::errors::FormatterError;
+
+use crate::args::{Args, SkipMode};
+use crate::batch::Batch;
+
+/// Time between polls when `--interval` isn't given.
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// What a file looked like at a poll: its modification time and size.
+type Stamp = Option<(SystemTime, u64)>;
+
+/// Converts `dir`, then reconverts changed files until the process is killed.
+///
+/// Only fails if the first conversion can't be set up (e.g. the config is
+/// invalid); later errors are reported and watching continues.
+pub fn watch(args: &Args, dir: &Path) -> Result<(), FormatterError> {
+    let interval = args.interval.map_or(DEFAULT_INTERVAL, Duration::from_millis);
+    let jobs = crate::jobs(args);
+    // Changed files are always converted, but the hash cache is kept up to date
+    let skip = args.skip_unchanged.filter(|&mode| mode == SkipMode::Hash);
+
+    let mut config_stamp = args.config.as_deref().and_then(stamp);
+    let mut batch = crate::new_batch(args, dir)?;
+    // Snapshot before converting, so edits made during the conversion are picked up next time
+    let mut stamps = snapshot(&batch).map_err(|e| crate::with_path(e, dir))?;
+    let summary = batch.run(args.skip_unchanged, jobs).map_err(|e| crate::with_path(e, dir))?;
+    crate::print_summary(dir, &summary);
+    println!("Watching {} for changes (Ctrl-C to stop)", dir.display());
+
+    loop {
+        thread::sleep(interval);
+
+        // Without --config there is nothing to reload: the stamps stay `None`
+        let mut reload = false;
+        let new_config_stamp = args.config.as_deref().and_then(stamp);
+        if new_config_stamp != config_stamp {
+            config_stamp = new_config_stamp;
+            match crate::new_batch(args, dir) {
+                Ok(reloaded) => {
+                    if let Some(config_path) = &args.config {
+                        println!("{} changed, converting all files", config_path.display());
+                    }
+                    batch = reloaded;
+                    reload = true;
+                }
+                Err(e) => {
+                    eprintln!("tform: keeping the previous config: {}", e);
+                    continue;
+                }
+            }
+        }
+
+        let current = match snapshot(&batch) {
+            Ok(current) => current,
+            Err(e) => {
+                eprintln!("tform: {}: {}", dir.display(), e);
+                continue;
+            }
+        };
+        let mut changed: Vec<PathBuf> = current
+            .iter()
+            .filter(|(rel, stamp)| reload || stamps.get(*rel) != Some(*stamp))
+            .map(|(rel, _)| rel.clone())
+            .collect();
+        let deleted: Vec<PathBuf> = stamps.keys().filter(|rel| !current.contains_key(*rel)).cloned().collect();
+        for rel in &deleted {
+            println!("Deleted: {}", dir.join(rel).display());
+            remove_output(&batch, rel, &current, &mut changed);
+        }
+        stamps = current;
+        if changed.is_empty() && deleted.is_empty() {
+            continue;
+        }
+        changed.sort();
+        changed.dedup();
+        for rel in &changed {
+            println!("Changed: {}", dir.join(rel).display());
+        }
+        // Also run without changes, to drop deleted files from the hash cache
+        let all: Vec<PathBuf> = stamps.keys().cloned().collect();
+        match batch.convert_files(&changed, &all, skip, jobs) {
+            Ok(summary) if !changed.is_empty() => crate::print_summary(dir, &summary),
+            Ok(_) => {}
+            Err(e) => eprintln!("tform: {}: {}", batch.dest.display(), e),
+        }
+    }
+}
+
+/// Removes the output of the deleted file `rel`, unless another of the
+/// `current` files is written there: that one is converted again instead, as
+/// it failed while the two collided.
+fn remove_output(batch: &Batch, rel: &Path, current: &BTreeMap<PathBuf, Stamp>, changed: &mut Vec<PathBuf>) {
+    let output = batch.output_path(rel);
+    if let Some(other) = current.keys().find(|other| batch.output_path(other) == output) {
+        changed.push(other.clone());
+        return;
+    }
+    match fs::remove_file(&output) {
+        Ok(()) => println!("Removed: {}", output.display()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => eprintln!("tform: {}: {}", output.display(), e),
+    }
+}
+
+/// The stamps of all files `batch` converts, by path relative to the input directory.
+fn snapshot(batch: &Batch) -> io::Result<BTreeMap<PathBuf, Stamp>> {
+    let files = batch.files()?;
+    Ok(files
+        .into_iter()
+        .map(|rel| {
+            let stamp = stamp(&batch.src.join(&rel));
+            (rel, stamp)
+        })
+        .collect())
+}
+
+fn stamp(path: &Path) -> Stamp {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
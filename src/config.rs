@@ -1,4 +1,3 @@
-// src/config.rs
 /*!
 Configuration module for `tform.io`.
 
@@ -11,7 +10,10 @@ use std::fs;
 use crate::errors::FormatterError;
 
 /// A set of user-definable rules for text formatting.
+///
+/// Fields missing from a config file keep their default values.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     /// If true, remove multiple consecutive spaces.
     pub remove_extra_spaces: bool,
@@ -19,11 +21,131 @@ pub struct Config {
     /// If true, detect lines starting with "#" as headings.
     pub detect_headings: bool,
 
+    /// If true (and `detect_headings` is on), a paragraph underlined with `===`
+    /// or `---` (three or more) becomes a level 1 or 2 heading instead of being
+    /// followed by a thematic break.
+    pub detect_setext_headings: bool,
+
+    /// If true, promote short standalone ALL CAPS lines to level 1 headings.
+    pub detect_all_caps_headings: bool,
+
+    /// If true, promote short standalone Title Case lines to level 2 headings.
+    pub detect_title_case_headings: bool,
+
+    /// If true, promote short standalone numbered lines such as "2.3 Results"
+    /// to headings, one level per number component. A single number followed
+    /// by a period, as in "1. Introduction", is an ordered list item instead;
+    /// "1 Introduction" is a heading.
+    pub detect_numbered_headings: bool,
+
     /// If true, detect bullet points in lines starting with "-", "*", "+", etc.
     pub detect_lists: bool,
 
-    /// Additional user-defined patterns that can override default detection rules.
-    pub custom_patterns: Vec<String>,
+    /// If true, detect lines starting with ">" (including email-style ">>") as quotes.
+    pub detect_quotes: bool,
+
+    /// If true, detect tables: pipe tables, tab-separated rows and space-aligned columns.
+    pub detect_tables: bool,
+
+    /// If true, treat runs of indented lines (outside lists, not continuing a
+    /// paragraph) as code blocks, keeping their whitespace exactly. A single
+    /// indented line followed directly by less indented text starts a paragraph.
+    pub detect_indented_code: bool,
+
+    /// Minimum indentation, in columns (a tab counts as 4), for indented code.
+    pub indented_code_width: usize,
+
+    /// If true, join words hyphenated across lines ("exam-" + "ple" becomes
+    /// "example"), keeping the hyphen in real compounds such as "long-term"
+    /// or "COVID-19".
+    pub repair_hyphenation: bool,
+
+    /// If true, join fragments of a word broken across lines without a hyphen
+    /// ("wit" + "hin" becomes "within") when the word list knows the joined word
+    /// and neither fragment. Off by default: a short word list can't tell every
+    /// real word apart from a fragment.
+    pub repair_split_words: bool,
+
+    /// If true, use the built-in list of common English words for the repairs above.
+    pub builtin_word_list: bool,
+
+    /// Optional path to an extra word list: one word per line, `#` starts a comment.
+    pub word_list: Option<String>,
+
+    /// If true, HTML output marks each block with the input line it came from
+    /// (`data-source-line="12"`), for scroll-sync in editors.
+    pub html_source_lines: bool,
+
+    /// Additional user-defined rules, applied before the built-in detection.
+    ///
+    /// Each entry is either a bare regex (matching lines become callouts) or a
+    /// full [`CustomRule`] table.
+    pub custom_patterns: Vec<CustomPattern>,
+}
+
+/// A single entry of `Config::custom_patterns`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CustomPattern {
+    /// Shorthand: a bare regex; matching lines are wrapped as a callout named "custom".
+    Pattern(String),
+    /// A fully specified rule.
+    Rule(CustomRule),
+}
+
+/// A named transformation rule driven by a regex.
+///
+/// In TOML:
+///
+/// ```toml
+/// [[custom_patterns]]
+/// name = "todo"
+/// pattern = "(?i)^todo:?\\s*(.*)$"
+/// action = "callout"
+/// priority = 10
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomRule {
+    /// Rule name; used as the callout kind in the rendered output.
+    pub name: String,
+
+    /// Regex matched against each (cleaned up) input line.
+    pub pattern: String,
+
+    /// What to do with a matching line.
+    #[serde(default)]
+    pub action: RuleAction,
+
+    /// Replacement template for `RuleAction::Replace` (supports `$1`, `${name}`, ...).
+    #[serde(default)]
+    pub template: Option<String>,
+
+    /// Heading level for `RuleAction::Heading` (1-6, defaults to 1).
+    #[serde(default)]
+    pub level: Option<u8>,
+
+    /// Rules with a higher priority are tried first; ties keep declaration order.
+    #[serde(default)]
+    pub priority: i32,
+}
+
+/// The action a `CustomRule` performs on a matching line.
+///
+/// For `Callout` and `Heading`, the text is the first capture group if the
+/// pattern has one, otherwise the whole line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    /// Wrap the line as a callout block.
+    #[default]
+    Callout,
+    /// Rewrite the line with `template`, then continue with the remaining rules
+    /// and the built-in detection.
+    Replace,
+    /// Promote the line to a heading.
+    Heading,
+    /// Drop the line entirely.
+    Drop,
 }
 
 impl Default for Config {
@@ -31,7 +153,20 @@ impl Default for Config {
         Self {
             remove_extra_spaces: true,
             detect_headings: true,
+            detect_setext_headings: true,
+            detect_all_caps_headings: false,
+            detect_title_case_headings: false,
+            detect_numbered_headings: false,
             detect_lists: true,
+            detect_quotes: true,
+            detect_tables: true,
+            detect_indented_code: true,
+            indented_code_width: 4,
+            repair_hyphenation: true,
+            repair_split_words: false,
+            builtin_word_list: true,
+            word_list: None,
+            html_source_lines: false,
             custom_patterns: vec![],
         }
     }
@@ -0,0 +1,125 @@
+/*!
+Pluggable block detection: the `BlockDetector` trait and the context detectors see.
+
+Every line of input is offered to the registered detectors in priority order
+(highest first); the first one to return a `Detection` claims the line. Lines
+nobody claims become paragraph text. The built-in detectors (code, quotes,
+tables, custom rules, headings, lists, ...) use the same trait, with the
+priorities listed in [`priority`].
+*/
+
+use crate::config::Config;
+use crate::errors::FormatterError;
+use crate::parser::{parse_inlines, Line, LineCursor, Parser};
+use crate::structures::{Block, Inline, Spanned};
+
+/// Priorities of the built-in detectors. Higher runs first; a detector with
+/// the same priority as a built-in runs after it.
+pub mod priority {
+    /// Fenced code blocks (```` ``` ```` or `~~~`).
+    pub const FENCED_CODE: i32 = 900;
+    /// Indented code blocks (`Config::detect_indented_code`).
+    pub const INDENTED_CODE: i32 = 800;
+    /// Block quotes (`> text`).
+    pub const QUOTE: i32 = 700;
+    /// Pipe, tab-separated and space-aligned tables.
+    pub const TABLE: i32 = 600;
+    /// User-defined rules from `Config::custom_patterns`.
+    pub const CUSTOM_RULES: i32 = 500;
+    /// ATX headings (`# Heading`).
+    pub const HEADING: i32 = 400;
+    /// Thematic breaks (`---`, `***`, ...).
+    pub const THEMATIC_BREAK: i32 = 300;
+    /// Bullet and numbered lists.
+    pub const LIST: i32 = 200;
+    /// Setext headings (a line underlined with `===` or `---`).
+    pub const SETEXT_HEADING: i32 = 100;
+    /// Heading heuristics for plain text (all caps, title case, numbering).
+    pub const HEADING_HEURISTICS: i32 = 0;
+}
+
+/// Recognizes a kind of block in the input.
+///
+/// Detectors must be `Send + Sync` so a `Parser` can be shared across threads.
+pub trait BlockDetector: Send + Sync {
+    /// Where this detector runs relative to the others; higher runs first.
+    fn priority(&self) -> i32;
+
+    /// Inspects the current line, returning `Some` to claim it.
+    ///
+    /// A detector may peek at and consume the lines that follow (through
+    /// `DetectContext::peek_line` and `DetectContext::next_line`) to build a
+    /// block spanning several lines. Returning `None` passes the line on to
+    /// the next detector, and must not consume anything.
+    fn detect(&self, ctx: &mut DetectContext) -> Result<Option<Detection>, FormatterError>;
+}
+
+/// What a detector made of the current line.
+#[derive(Debug, Clone)]
+pub enum Detection {
+    /// The line starts a new block; any paragraph in progress is finished first.
+    Block(Block),
+    /// The line is paragraph text, continuing the current paragraph if there is one.
+    Paragraph(String),
+    /// The line is consumed without producing output.
+    Skip,
+}
+
+/// The current line and parser state, as seen by a `BlockDetector`.
+pub struct DetectContext<'a, 'b> {
+    pub(crate) parser: &'a Parser,
+    pub(crate) lines: &'a mut LineCursor<'b>,
+    pub(crate) line: &'a Line,
+    pub(crate) text: String,
+    pub(crate) in_paragraph: bool,
+}
+
+impl DetectContext<'_, '_> {
+    /// The current line, with its indentation and raw text.
+    pub fn line(&self) -> &Line {
+        self.line
+    }
+
+    /// The current line's text, trimmed, with extra spaces collapsed and any
+    /// rewrites by earlier detectors applied.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Rewrites the current line's text for the detectors that run after this one.
+    pub fn set_text(&mut self, text: impl Into<String>) {
+        self.text = text.into();
+    }
+
+    /// Whether a paragraph is in progress (the previous line was paragraph text).
+    pub fn in_paragraph(&self) -> bool {
+        self.in_paragraph
+    }
+
+    /// The configuration the parser was built with.
+    pub fn config(&self) -> &Config {
+        self.parser.config()
+    }
+
+    /// Peeks `n` lines past the current one without consuming anything.
+    pub fn peek_line(&mut self, n: usize) -> Result<Option<&Line>, FormatterError> {
+        self.lines.peek_nth(n)
+    }
+
+    /// Consumes the line after the current one, as part of the claimed block.
+    pub fn next_line(&mut self) -> Result<Option<Line>, FormatterError> {
+        self.lines.next_line()
+    }
+
+    /// Parses lines (e.g. the contents of a container block) into blocks,
+    /// using all registered detectors. Use `Line::with_text` to strip a
+    /// container's markers while keeping source positions.
+    pub fn parse_nested(&self, lines: Vec<Line>) -> Result<Vec<Spanned<Block>>, FormatterError> {
+        self.parser.parse_lines(lines, self.lines)
+    }
+
+    /// Parses inline markup (emphasis, code spans, links, ...).
+    pub fn parse_inlines(&self, text: &str) -> Vec<Inline> {
+        parse_inlines(text)
+    }
+}
@@ -9,6 +9,7 @@ use std::io;
 #[derive(Debug)]
 pub enum FormatterError {
     IoError(io::Error),
+    WriteError(io::Error), // Failure writing rendered output
     ParseError(String), // Could be from JSON/TOML or text parsing
 }
 
@@ -16,12 +17,20 @@ impl Display for FormatterError {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
         match self {
             FormatterError::IoError(e) => write!(f, "IO Error: {}", e),
+            FormatterError::WriteError(e) => write!(f, "Write Error: {}", e),
             FormatterError::ParseError(e) => write!(f, "Parse Error: {}", e),
         }
     }
 }
 
-impl Error for FormatterError {}
+impl Error for FormatterError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FormatterError::IoError(e) | FormatterError::WriteError(e) => Some(e),
+            FormatterError::ParseError(_) => None,
+        }
+    }
+}
 
 impl From<io::Error> for FormatterError {
     fn from(err: io::Error) -> Self {
@@ -0,0 +1,85 @@
+/*!
+Escaping helpers used by the HTML and Markdown renderers.
+
+Input is always treated as plain text: `&lt;` in the source is shown as the
+literal characters `&lt;`, never decoded into `<`.
+*/
+
+/// Escape text for use between HTML tags.
+pub fn escape_text(text: &str) -> String {
+    escape(text, false)
+}
+
+/// Escape text for use inside a double- or single-quoted attribute value.
+pub fn escape_attr(value: &str) -> String {
+    escape(value, true)
+}
+
+/// Escape a URL for an `href`/`src` attribute, neutralizing script-capable schemes.
+pub fn escape_url(url: &str) -> String {
+    // Browsers ignore whitespace and control characters inside the scheme
+    let scheme: String = url
+        .chars()
+        .take_while(|&c| c != ':')
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_ascii_lowercase();
+
+    let has_scheme = url.contains(':');
+    if has_scheme && matches!(scheme.as_str(), "javascript" | "vbscript" | "data") {
+        return "#".to_string();
+    }
+    escape_attr(url)
+}
+
+/// Backslash-escape the characters of `text` that inline Markdown would read
+/// as markup, so it reads back literally.
+///
+/// `before` and `after` are the characters around `text` in the output (`None`
+/// at the edges). Punctuation is only escaped where its neighbours could make
+/// it markup, so `5 * 3` and `snake_case` stay as they are. `[` is always
+/// escaped, and with `in_label` (inside a link's text) so is `]`.
+pub fn escape_markdown(text: &str, before: Option<char>, after: Option<char>, in_label: bool) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let space = |c: Option<char>| c.is_none_or(char::is_whitespace);
+    let alnum = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+
+    let mut output = String::with_capacity(text.len());
+    for (i, &c) in chars.iter().enumerate() {
+        let prev = if i == 0 { before } else { Some(chars[i - 1]) };
+        let next = chars.get(i + 1).copied().or(after);
+        let escape = match c {
+            '\\' => next.is_none_or(|n| n.is_ascii_punctuation() || n == '\n'),
+            '`' => true,
+            // Between spaces `*` can neither open nor close emphasis
+            '*' => !(space(prev) && space(next)),
+            // `_` also can't open after a letter or close before one
+            '_' => !((space(next) || alnum(prev)) && (space(prev) || alnum(next))),
+            // Only links open brackets; inside a label, a `]` would end it early
+            '[' => true,
+            ']' => in_label || next == Some('('),
+            '!' => next == Some('['),
+            _ => false,
+        };
+        if escape {
+            output.push('\\');
+        }
+        output.push(c);
+    }
+    output
+}
+
+fn escape(text: &str, attr: bool) -> String {
+    let mut output = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' if attr => output.push_str("&#39;"),
+            _ => output.push(c),
+        }
+    }
+    output
+}
@@ -2,13 +2,16 @@
 Formatter: orchestrates parsing and then renders as Markdown or HTML.
 */
 
-use std::io::{BufReader, Read};
+use std::io::{self, BufReader, Read, Write};
 
 use crate::{
     config::Config,
+    detector::BlockDetector,
     errors::FormatterError,
-    parser::Parser,
-    structures::{Block, Document},
+    escape::{escape_attr, escape_markdown, escape_text, escape_url},
+    parser::{emphasis_closer, parse_enumerator, Parser},
+    renderer::{HtmlRenderer, JsonRenderer, MarkdownRenderer, Renderer},
+    structures::{Alignment, Block, Document, Inline, ListItem, ListStyle, Spanned, Table},
 };
 
 /// Main entry point for text formatting.
@@ -18,84 +21,733 @@ pub struct Formatter {
 
 impl Formatter {
     /// Create a new Formatter from a `Config`.
+    ///
+    /// Invalid custom patterns are left out and a word list that can't be read
+    /// is ignored; use `Formatter::try_new` to report those instead.
     pub fn new(config: Config) -> Self {
         let parser = Parser::new(config);
         Formatter { parser }
     }
 
+    /// Create a new Formatter from a `Config`, reporting invalid custom patterns
+    /// and unreadable word lists.
+    pub fn try_new(config: Config) -> Result<Self, FormatterError> {
+        let parser = Parser::try_new(config)?;
+        Ok(Formatter { parser })
+    }
+
+    /// Register a custom `BlockDetector` with the parser.
+    pub fn add_detector<D: BlockDetector + 'static>(&mut self, detector: D) {
+        self.parser.add_detector(detector);
+    }
+
+    /// Parse input (via `Read`) into a `Document`, which can then be rendered
+    /// several times without parsing again.
+    pub fn parse<R: Read>(&self, reader: R) -> Result<Document, FormatterError> {
+        self.parser.parse(BufReader::new(reader))
+    }
+
+    /// Render a parsed `Document` as Markdown.
+    pub fn render_markdown(&self, doc: &Document) -> String {
+        document_to_markdown(doc)
+    }
+
+    /// Render a parsed `Document` as HTML.
+    pub fn render_html(&self, doc: &Document) -> String {
+        document_to_html(doc, self.parser.config().html_source_lines)
+    }
+
+    /// Render a parsed `Document` with a custom `Renderer`.
+    pub fn render_with(&self, renderer: &dyn Renderer, doc: &Document) -> Result<String, FormatterError> {
+        let mut output = Vec::new();
+        renderer.document_start(&mut output).map_err(FormatterError::WriteError)?;
+        for block in &doc.blocks {
+            renderer.block(block, &mut output).map_err(FormatterError::WriteError)?;
+        }
+        renderer.document_end(&mut output).map_err(FormatterError::WriteError)?;
+        String::from_utf8(output)
+            .map_err(|e| FormatterError::ParseError(format!("renderer produced invalid UTF-8: {}", e)))
+    }
+
     /// Format input (via `Read`) into Markdown.
+    ///
+    /// Formatting the output again leaves it unchanged, except with the
+    /// layout-based options listed on `write_markdown`.
     pub fn format_to_markdown<R: Read>(&self, reader: R) -> Result<String, FormatterError> {
-        let buf_reader = BufReader::new(reader);
-        let doc = self.parser.parse(buf_reader)?;
-        Ok(document_to_markdown(&doc))
+        let doc = self.parse(reader)?;
+        Ok(self.render_markdown(&doc))
     }
 
     /// Format input (via `Read`) into HTML.
     pub fn format_to_html<R: Read>(&self, reader: R) -> Result<String, FormatterError> {
-        let buf_reader = BufReader::new(reader);
-        let doc = self.parser.parse(buf_reader)?;
-        Ok(document_to_html(&doc))
+        let doc = self.parse(reader)?;
+        Ok(self.render_html(&doc))
+    }
+
+    /// Format input (via `Read`) into a JSON document tree (see `schema`).
+    pub fn format_to_json<R: Read>(&self, reader: R) -> Result<String, FormatterError> {
+        self.format_with(&JsonRenderer::default(), reader)
+    }
+
+    /// Format input (via `Read`) into Markdown, writing each block to `writer`
+    /// as soon as it has been parsed.
+    pub fn format_markdown_to<R: Read, W: Write>(&self, reader: R, writer: W) -> Result<(), FormatterError> {
+        self.format_with_to(&MarkdownRenderer::default(), reader, writer)
+    }
+
+    /// Format input (via `Read`) into HTML, writing each block to `writer`
+    /// as soon as it has been parsed.
+    pub fn format_html_to<R: Read, W: Write>(&self, reader: R, writer: W) -> Result<(), FormatterError> {
+        let renderer = HtmlRenderer {
+            source_lines: self.parser.config().html_source_lines,
+        };
+        self.format_with_to(&renderer, reader, writer)
+    }
+
+    /// Format input (via `Read`) into JSON, writing each block to `writer`
+    /// as soon as it has been parsed.
+    pub fn format_json_to<R: Read, W: Write>(&self, reader: R, writer: W) -> Result<(), FormatterError> {
+        self.format_with_to(&JsonRenderer::default(), reader, writer)
+    }
+
+    /// Format input (via `Read`) with a custom `Renderer`.
+    pub fn format_with<R: Read>(&self, renderer: &dyn Renderer, reader: R) -> Result<String, FormatterError> {
+        let mut output = Vec::new();
+        self.format_with_to(renderer, reader, &mut output)?;
+        String::from_utf8(output)
+            .map_err(|e| FormatterError::ParseError(format!("renderer produced invalid UTF-8: {}", e)))
+    }
+
+    /// Format input (via `Read`) with a custom `Renderer`, writing each block to
+    /// `writer` as soon as it has been parsed.
+    pub fn format_with_to<R: Read, W: Write>(
+        &self,
+        renderer: &dyn Renderer,
+        reader: R,
+        mut writer: W,
+    ) -> Result<(), FormatterError> {
+        renderer.document_start(&mut writer).map_err(FormatterError::WriteError)?;
+        for block in self.parser.parse_iter(BufReader::new(reader)) {
+            renderer.block(&block?, &mut writer).map_err(FormatterError::WriteError)?;
+        }
+        renderer.document_end(&mut writer).map_err(FormatterError::WriteError)?;
+        writer.flush().map_err(FormatterError::WriteError)
     }
 }
 
 /// Convert intermediate Document to Markdown string.
 fn document_to_markdown(doc: &Document) -> String {
-    let mut output = String::new();
+    let mut output = Vec::new();
+    write_markdown(&doc.blocks, &mut output).expect("writing to a Vec cannot fail");
+    String::from_utf8(output).expect("rendered Markdown is valid UTF-8")
+}
 
-    for block in &doc.blocks {
-        match block {
-            Block::Heading(level, text) => {
-                let hashes = "#".repeat(*level as usize);
-                output.push_str(&format!("{} {}\n\n", hashes, text));
+/// Render a sequence of blocks as Markdown into `output`.
+///
+/// Formatting the output again leaves it unchanged, except with the heading
+/// heuristics (`Config::detect_all_caps_headings` and the like), or with table
+/// detection but without `Config::remove_extra_spaces`. Those look at how lines
+/// are laid out, which rendering changes: wrapped lines are joined, blocks get
+/// blank lines around them and escaping shifts columns.
+pub fn write_markdown(blocks: &[Spanned<Block>], output: &mut impl Write) -> io::Result<()> {
+    let mut previous = None;
+    for block in blocks {
+        previous = write_markdown_block(block, previous, output)?;
+    }
+    Ok(())
+}
+
+/// Render one block as Markdown into `output`.
+///
+/// `previous` is the marker character of the block before, if that was a list:
+/// a list right after another one uses different markers, or the two would
+/// merge on reparse. Returns the marker character this block used, if any.
+pub(crate) fn write_markdown_block(
+    block: &Block,
+    previous: Option<char>,
+    output: &mut impl Write,
+) -> io::Result<Option<char>> {
+    match block {
+        Block::Heading(level, text) => {
+            let hashes = "#".repeat(*level as usize);
+            write!(output, "{} {}\n\n", hashes, inlines_to_markdown(&without_tabs(text)))?;
+        }
+        Block::Paragraph(text) => {
+            write!(output, "{}\n\n", paragraph_to_markdown(text))?;
+        }
+        Block::List(items) => {
+            // "*" is avoided: "* ***" would read as a thematic break
+            let bullet = if previous == Some('-') { '+' } else { '-' };
+            for item in items {
+                list_item_to_markdown(&format!("{} ", bullet), item, output)?;
             }
-            Block::Paragraph(text) => {
-                output.push_str(text);
-                output.push_str("\n\n");
+            output.write_all(b"\n")?;
+            return Ok(Some(bullet));
+        }
+        Block::OrderedList(_, start, items) => {
+            // Markdown only knows decimal numbering; the start number is kept
+            let delimiter = if previous == Some('.') { ')' } else { '.' };
+            for (n, item) in items.iter().enumerate() {
+                let marker = format!("{}{} ", *start + n as u32, delimiter);
+                list_item_to_markdown(&marker, item, output)?;
             }
-            Block::List(items) => {
-                for item in items {
-                    output.push_str(&format!("- {}\n", item));
+            output.write_all(b"\n")?;
+            return Ok(Some(delimiter));
+        }
+        Block::Table(table) => table_to_markdown(table, output)?,
+        Block::ThematicBreak => output.write_all(b"---\n\n")?,
+        Block::CodeBlock(info, code) => {
+            // The fence must be longer than any fence-like run inside the code
+            let info = info.as_deref().unwrap_or("");
+            let fence_char = if info.contains('`') { '~' } else { '`' };
+            let longest = code
+                .lines()
+                .map(|l| l.trim_start().chars().take_while(|&c| c == fence_char).count())
+                .max()
+                .unwrap_or(0);
+            let fence = fence_char.to_string().repeat(longest.max(2) + 1);
+            // Keep an info string like "~~~x" from lengthening the fence
+            let gap = if info.starts_with(fence_char) { " " } else { "" };
+            write!(output, "{fence}{gap}{info}\n{code}\n{fence}\n\n")?;
+        }
+        Block::Callout(name, text) => {
+            // GitHub/Obsidian-style alert syntax
+            writeln!(output, "> [!{}]", name.to_uppercase())?;
+            if !text.is_empty() {
+                for line in paragraph_to_markdown(text).lines() {
+                    writeln!(output, "> {}", line)?;
                 }
-                output.push('\n');
             }
-            Block::CodeBlock(code) => {
-                output.push_str("```\n");
-                output.push_str(code);
-                output.push_str("\n```\n\n");
+            output.write_all(b"\n")?;
+        }
+        Block::Quote(inner) => {
+            if inner.is_empty() {
+                output.write_all(b">\n")?;
+            }
+            for line in nested_markdown(inner).trim_end().lines() {
+                if line.is_empty() {
+                    output.write_all(b">\n")?;
+                } else {
+                    writeln!(output, "> {}", line)?;
+                }
             }
+            output.write_all(b"\n")?;
         }
     }
-    output
+    Ok(None)
 }
 
-/// Convert intermediate Document to HTML string.
-fn document_to_html(doc: &Document) -> String {
+/// Render nested blocks to a `String`, so they can be prefixed line by line.
+fn nested_markdown(blocks: &[Spanned<Block>]) -> String {
+    let mut nested = Vec::new();
+    write_markdown(blocks, &mut nested).expect("writing to a Vec cannot fail");
+    String::from_utf8(nested).expect("rendered Markdown is valid UTF-8")
+}
+
+/// Render one list item; nested content is indented to line up with the item text.
+fn list_item_to_markdown(marker: &str, item: &ListItem, output: &mut impl Write) -> io::Result<()> {
+    let indent = " ".repeat(marker.len());
+    if item.text.is_empty() && !item.children.is_empty() {
+        // An item starting with another block (e.g. "1. ```"): a bare marker
+        // isn't an item, so the block goes on the marker line
+        let nested = nested_markdown(&item.children);
+        for (i, line) in nested.trim_end().lines().enumerate() {
+            match (i, line.is_empty()) {
+                // "- ---" would be read as a thematic break, so write the rule as "***"
+                (0, _) if line == "---" => writeln!(output, "{}***", marker)?,
+                (0, _) => writeln!(output, "{}{}", marker, line)?,
+                (_, true) => output.write_all(b"\n")?,
+                (_, false) => writeln!(output, "{}{}", indent, line)?,
+            }
+        }
+        return Ok(());
+    }
+    // Items with tabs could read back as a tab-separated table, like hard-broken lines
+    let mut text = paragraph_to_markdown(&without_tabs(&item.text));
+    // "- --" would be read as a thematic break, so escape the text's first dash
+    let first_line = format!("{}{}", marker, text.lines().next().unwrap_or_default());
+    let bullet = marker.chars().next().filter(|c| matches!(c, '-' | '*'));
+    if bullet.is_some_and(|c| is_rule(&first_line, c)) {
+        text.insert(0, '\\');
+    }
+    writeln!(output, "{}{}", marker, text.replace('\n', &format!("\n{}", indent)))?;
+
+    if item.children.is_empty() {
+        return Ok(());
+    }
+
+    // A paragraph directly below the item text would be merged into it on
+    // reparse, a rule would underline it as a setext heading, and a numbered
+    // list not starting at 1 can't interrupt it
+    let separate = match item.children.first().map(|b| &b.node) {
+        Some(Block::Paragraph(_) | Block::ThematicBreak) => true,
+        Some(Block::OrderedList(style, start, _)) => {
+            *start != 1 || matches!(style, ListStyle::UpperAlpha | ListStyle::UpperRoman)
+        }
+        _ => false,
+    };
+    if separate {
+        output.write_all(b"\n")?;
+    }
+
+    for line in nested_markdown(&item.children).trim_end().lines() {
+        if line.is_empty() {
+            output.write_all(b"\n")?;
+        } else {
+            writeln!(output, "{}{}", indent, line)?;
+        }
+    }
+    Ok(())
+}
+
+/// Render a table as a GFM pipe table, padding cells so the columns line up.
+fn table_to_markdown(table: &Table, output: &mut impl Write) -> io::Result<()> {
+    // Pipes inside cells would split them on reparse
+    let render = |cells: &[Vec<Inline>]| -> Vec<String> {
+        cells
+            .iter()
+            .map(|cell| inlines_to_markdown(cell).replace('|', "\\|"))
+            .collect()
+    };
+    let header = render(&table.header);
+    let rows: Vec<Vec<String>> = table.rows.iter().map(|row| render(row)).collect();
+
+    let widths: Vec<usize> = (0..header.len())
+        .map(|col| {
+            std::iter::once(&header)
+                .chain(&rows)
+                .map(|row| row[col].chars().count())
+                .max()
+                .unwrap_or(0)
+                .max(3)
+        })
+        .collect();
+
+    let write_row = |cells: &[String], output: &mut dyn Write| -> io::Result<()> {
+        output.write_all(b"|")?;
+        for (cell, width) in cells.iter().zip(&widths) {
+            let pad = width - cell.chars().count();
+            write!(output, " {}{} |", cell, " ".repeat(pad))?;
+        }
+        output.write_all(b"\n")
+    };
+
+    write_row(&header, output)?;
+    output.write_all(b"|")?;
+    for (alignment, width) in table.alignments.iter().zip(&widths) {
+        let delimiter = match alignment {
+            Alignment::None => "-".repeat(*width),
+            Alignment::Left => format!(":{}", "-".repeat(width - 1)),
+            Alignment::Center => format!(":{}:", "-".repeat(width - 2)),
+            Alignment::Right => format!("{}:", "-".repeat(width - 1)),
+        };
+        write!(output, " {} |", delimiter)?;
+    }
+    output.write_all(b"\n")?;
+    for row in &rows {
+        write_row(row, output)?;
+    }
+    output.write_all(b"\n")
+}
+
+/// Render paragraph text as Markdown, escaping the start of any line that
+/// would otherwise read back as another block (a heading, list item, quote, ...).
+fn paragraph_to_markdown(inlines: &[Inline]) -> String {
+    let text = inlines_to_markdown(inlines);
+    if !text.contains('\n') {
+        return escape_block_start(&text);
+    }
+    // Hard-broken lines holding tabs could read back as a tab-separated table
+    let text = if text.contains('\t') { inlines_to_markdown(&without_tabs(inlines)) } else { text };
+    text.split('\n').map(escape_block_start).collect::<Vec<_>>().join("\n")
+}
+
+/// `inlines` with the tabs in their text (but not in code) replaced by spaces.
+fn without_tabs(inlines: &[Inline]) -> Vec<Inline> {
+    inlines
+        .iter()
+        .map(|inline| match inline {
+            Inline::Text(text) => Inline::Text(text.replace('\t', " ")),
+            Inline::Emphasis(inner) => Inline::Emphasis(without_tabs(inner)),
+            Inline::Strong(inner) => Inline::Strong(without_tabs(inner)),
+            Inline::Link(text, url) => Inline::Link(without_tabs(text), url.clone()),
+            other => other.clone(),
+        })
+        .collect()
+}
+
+/// Escape the first character of a line (or the delimiter of a list marker)
+/// if the parser would otherwise take the line for the start of a block.
+fn escape_block_start(line: &str) -> String {
+    let mut chars = line.chars();
+    let (Some(first), second) = (chars.next(), chars.next()) else {
+        return String::new();
+    };
+    // "---", "* * *", "===" and the like: thematic breaks and setext underlines
+    let is_rule = is_rule(line, first);
+
+    let escape_first = match first {
+        // "|" also starts the rows of pipe tables without a delimiter row
+        '#' | '>' | '|' => true,
+        '-' | '+' | '*' => second.is_none_or(char::is_whitespace) || is_rule,
+        '_' | '=' => is_rule,
+        // A backtick fence has no backticks after it, so a code span never looks like one
+        '`' => line.starts_with("```") && !line.trim_start_matches('`').contains('`'),
+        '~' => line.starts_with("~~~"),
+        _ => false,
+    };
+    if escape_first {
+        return format!("\\{}", line);
+    }
+    // Lines like "|" or "--- | ---" could be read as a pipe table (row)
+    if line.contains('|') && line.chars().all(|c| matches!(c, '|' | '-' | ':' | ' ' | '\t')) {
+        return line.replace('|', "\\|");
+    }
+    match list_marker_delimiter(line) {
+        Some(at) => format!("{}\\{}", &line[..at], &line[at..]),
+        None => line.to_string(),
+    }
+}
+
+/// Whether `line` is three or more `c`s, with nothing else but spaces and tabs.
+fn is_rule(line: &str, c: char) -> bool {
+    line.chars().filter(|&ch| ch == c).count() >= 3 && line.chars().all(|ch| ch == c || ch == ' ' || ch == '\t')
+}
+
+/// Where the delimiter of an ordered list marker ("1.", "b)", "(iv)") starting
+/// `line` is, if the parser would read one there.
+fn list_marker_delimiter(line: &str) -> Option<usize> {
+    let (marker, at, rest) = match line.strip_prefix('(') {
+        Some(inner) => {
+            let end = inner.find(')')?;
+            (&inner[..end], 0, &inner[end + 1..])
+        }
+        None => {
+            let end = line.find(['.', ')'])?;
+            (&line[..end], end, &line[end + 1..])
+        }
+    };
+    let is_marker = !marker.is_empty()
+        && marker.chars().all(|c| c.is_ascii_alphanumeric())
+        && rest.starts_with(char::is_whitespace)
+        && !rest.trim().is_empty()
+        && parse_enumerator(marker, None).is_some();
+    is_marker.then_some(at)
+}
+
+/// Render inline content as Markdown that reads back as the same inlines.
+///
+/// Text is escaped where its neighbours would make it markup, and emphasis
+/// uses `*` or `_`, whichever pairs up the same way on reparse. The few shapes
+/// Markdown can't express at all (e.g. emphasis both inside a word and around
+/// emphasis that is too) are written with escaped `*`s, so they read back as
+/// text, and formatting again keeps them that way.
+fn inlines_to_markdown(inlines: &[Inline]) -> String {
+    let context = InlineContext {
+        before: None,
+        after: None,
+        enclosing: None,
+        in_label: false,
+        retry: true,
+    };
+    render_inlines(inlines, context).0
+}
+
+/// Where a run of inlines is rendered.
+#[derive(Clone, Copy)]
+struct InlineContext {
+    /// The characters around the run in the output (`None` at the edges)
+    before: Option<char>,
+    after: Option<char>,
+    /// The delimiter of the emphasis the run is inside, if any
+    enclosing: Option<char>,
+    /// Whether the run is a link label
+    in_label: bool,
+    /// Whether emphasis may fall back to its other delimiter
+    retry: bool,
+}
+
+/// Render inlines, and whether all of the emphasis among them reads back as emphasis.
+fn render_inlines(inlines: &[Inline], context: InlineContext) -> (String, bool) {
     let mut output = String::new();
+    let mut reads_back = true;
+    for (i, inline) in inlines.iter().enumerate() {
+        let prev = output.chars().next_back().or(context.before);
+        let next = inlines.get(i + 1).map_or(context.after, first_char);
+        match inline {
+            Inline::Text(text) => output.push_str(&escape_markdown(text, prev, next, context.in_label)),
+            Inline::Emphasis(inner) | Inline::Strong(inner) => {
+                let run = if matches!(inline, Inline::Emphasis(_)) { 1 } else { 2 };
+                let (emphasis, ok) = emphasis_to_markdown(inner, run, prev, next, context);
+                output.push_str(&emphasis);
+                reads_back &= ok;
+            }
+            Inline::Code(code) => {
+                // Use a backtick run longer than any inside the code, and pad code
+                // that would otherwise lose a backtick or a space on reparse
+                let longest = code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+                let fence = "`".repeat(longest + 1);
+                let spaced = code.starts_with(' ') && code.ends_with(' ') && !code.trim().is_empty();
+                let pad = if code.starts_with('`') || code.ends_with('`') || spaced { " " } else { "" };
+                output.push_str(&format!("{fence}{pad}{code}{pad}{fence}"));
+            }
+            Inline::Link(text, url) => {
+                // Emphasis outside doesn't look into the label, so it starts afresh
+                let label = InlineContext {
+                    before: Some('['),
+                    after: Some(']'),
+                    enclosing: None,
+                    in_label: true,
+                    ..context
+                };
+                let (text, ok) = render_inlines(text, label);
+                output.push_str(&format!("[{}]({})", text, url));
+                reads_back &= ok;
+            }
+            Inline::Image(alt, url) => output.push_str(&format!("![{}]({})", alt, url)),
+            Inline::LineBreak => output.push_str("\\\n"),
+        }
+    }
+    (output, reads_back)
+}
+
+/// The first character an inline is rendered with, as far as escaping its
+/// neighbour is concerned (emphasis may start with `_` instead).
+fn first_char(inline: &Inline) -> Option<char> {
+    match inline {
+        Inline::Text(text) => text.chars().next(),
+        Inline::Emphasis(_) | Inline::Strong(_) => Some('*'),
+        Inline::Code(_) => Some('`'),
+        Inline::Link(..) => Some('['),
+        Inline::Image(..) => Some('!'),
+        Inline::LineBreak => Some('\\'),
+    }
+}
+
+/// Render emphasis (`run` 1) or strong emphasis (`run` 2) between `prev` and
+/// `next`, and whether it and the emphasis inside it read back.
+///
+/// The delimiter the enclosing emphasis doesn't use is preferred. If the
+/// emphasis inside doesn't read back within it, the other one is tried, and if
+/// neither pairs up here the delimiters are written as escaped text.
+fn emphasis_to_markdown(
+    inner: &[Inline],
+    run: usize,
+    prev: Option<char>,
+    next: Option<char>,
+    context: InlineContext,
+) -> (String, bool) {
+    let order = if context.enclosing == Some('*') { ['_', '*'] } else { ['*', '_'] };
+    let render = |delim: char, retry: bool| {
+        let content = InlineContext {
+            before: Some(delim),
+            after: Some(delim),
+            enclosing: Some(delim),
+            retry,
+            ..context
+        };
+        let (content, nested) = render_inlines(inner, content);
+        let delims = delim.to_string().repeat(run);
+        let ok = emphasis_reads_back(&content, delim, run, prev, next);
+        (ok, nested, format!("{delims}{content}{delims}"))
+    };
+
+    let (ok, nested, preferred) = render(order[0], context.retry);
+    if ok && nested {
+        return (preferred, true);
+    }
+    // Only one level retries, which keeps deep nesting linear
+    let other = context.retry.then(|| render(order[1], false));
+    match other {
+        Some((true, true, other)) => return (other, true),
+        _ if ok => return (preferred, false),
+        Some((true, _, other)) => return (other, false),
+        _ => {}
+    }
+
+    // `*` next to text is always escaped, so the delimiters read back as text
+    let delims = Inline::Text("*".repeat(run));
+    let literal: Vec<Inline> = std::iter::once(delims.clone())
+        .chain(inner.iter().cloned())
+        .chain(std::iter::once(delims))
+        .collect();
+    let context = InlineContext {
+        before: prev,
+        after: next,
+        retry: false,
+        ..context
+    };
+    (render_inlines(&literal, context).0, false)
+}
 
-    for block in &doc.blocks {
-        match block {
+/// Whether `content` between runs of `run` `delim`s reads back as emphasis
+/// around exactly that content, given the characters around the runs.
+fn emphasis_reads_back(content: &str, delim: char, run: usize, prev: Option<char>, next: Option<char>) -> bool {
+    let alnum = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+    let first = content.chars().next();
+    // The opener: not part of a longer run, followed by text, and for `_` not inside a word
+    let opens = prev != Some(delim)
+        && first.is_some_and(|c| c != delim && !c.is_whitespace())
+        && (delim == '*' || !alnum(prev));
+    // The closer: the first one after the opener, and for `_` not inside a word
+    let closes = || {
+        let closing = delim.to_string().repeat(run);
+        emphasis_closer(&format!("{content}{closing}"), delim, run) == Some(content.chars().count())
+            && (delim == '*' || !alnum(next))
+    };
+    opens && closes()
+}
+
+/// Convert intermediate Document to HTML string.
+fn document_to_html(doc: &Document, source_lines: bool) -> String {
+    let mut output = Vec::new();
+    blocks_to_html(&doc.blocks, source_lines, &mut output).expect("writing to a Vec cannot fail");
+    String::from_utf8(output).expect("rendered HTML is valid UTF-8")
+}
+
+/// Render a sequence of blocks as HTML into `output`.
+pub fn write_html(blocks: &[Spanned<Block>], output: &mut impl Write) -> io::Result<()> {
+    blocks_to_html(blocks, false, output)
+}
+
+/// Render blocks as HTML; with `source_lines`, each block's opening tag gets a
+/// `data-source-line` attribute so editors can sync scrolling with the input.
+pub(crate) fn blocks_to_html(
+    blocks: &[Spanned<Block>],
+    source_lines: bool,
+    output: &mut impl Write,
+) -> io::Result<()> {
+    for block in blocks {
+        let attr = if source_lines {
+            format!(" data-source-line=\"{}\"", block.span.start.line)
+        } else {
+            String::new()
+        };
+        match &block.node {
             Block::Heading(level, text) => {
-                output.push_str(&format!("<h{lvl}>{}</h{lvl}>\n", text, lvl = level));
+                let text = inlines_to_html(text);
+                writeln!(output, "<h{lvl}{attr}>{}</h{lvl}>", text, lvl = level)?;
             }
             Block::Paragraph(text) => {
-                output.push_str("<p>");
-                output.push_str(text);
-                output.push_str("</p>\n");
+                writeln!(output, "<p{attr}>{}</p>", inlines_to_html(text))?;
             }
             Block::List(items) => {
-                output.push_str("<ul>\n");
+                writeln!(output, "<ul{attr}>")?;
                 for item in items {
-                    output.push_str(&format!("<li>{}</li>\n", item));
+                    list_item_to_html(item, source_lines, output)?;
+                }
+                output.write_all(b"</ul>\n")?;
+            }
+            Block::OrderedList(style, start, items) => {
+                write!(output, "<ol{attr}")?;
+                match style {
+                    ListStyle::Decimal => {}
+                    ListStyle::LowerAlpha => output.write_all(b" type=\"a\"")?,
+                    ListStyle::UpperAlpha => output.write_all(b" type=\"A\"")?,
+                    ListStyle::LowerRoman => output.write_all(b" type=\"i\"")?,
+                    ListStyle::UpperRoman => output.write_all(b" type=\"I\"")?,
                 }
-                output.push_str("</ul>\n");
+                if *start != 1 {
+                    write!(output, " start=\"{}\"", start)?;
+                }
+                output.write_all(b">\n")?;
+                for item in items {
+                    list_item_to_html(item, source_lines, output)?;
+                }
+                output.write_all(b"</ol>\n")?;
+            }
+            Block::Table(table) => table_to_html(table, &attr, output)?,
+            Block::ThematicBreak => writeln!(output, "<hr{attr}>")?,
+            Block::CodeBlock(info, code) => {
+                // The first word of the info string is the language
+                match info.as_deref().and_then(|i| i.split_whitespace().next()) {
+                    Some(lang) => write!(output, "<pre{attr}><code class=\"language-{}\">", escape_attr(lang))?,
+                    None => write!(output, "<pre{attr}><code>")?,
+                }
+                writeln!(output, "{}</code></pre>", escape_text(code))?;
+            }
+            Block::Callout(name, text) => {
+                writeln!(
+                    output,
+                    "<div class=\"callout callout-{}\"{attr}>{}</div>",
+                    escape_attr(name),
+                    inlines_to_html(text)
+                )?;
+            }
+            Block::Quote(inner) => {
+                writeln!(output, "<blockquote{attr}>")?;
+                blocks_to_html(inner, source_lines, output)?;
+                output.write_all(b"</blockquote>\n")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Render inline content as HTML.
+fn inlines_to_html(inlines: &[Inline]) -> String {
+    let mut output = String::new();
+    for inline in inlines {
+        match inline {
+            Inline::Text(text) => output.push_str(&escape_text(text)),
+            Inline::Emphasis(inner) => {
+                output.push_str(&format!("<em>{}</em>", inlines_to_html(inner)));
             }
-            Block::CodeBlock(code) => {
-                output.push_str("<pre><code>");
-                // For real usage, you may need to escape HTML inside `code`.
-                output.push_str(code);
-                output.push_str("</code></pre>\n");
+            Inline::Strong(inner) => {
+                output.push_str(&format!("<strong>{}</strong>", inlines_to_html(inner)));
             }
+            Inline::Code(code) => {
+                output.push_str(&format!("<code>{}</code>", escape_text(code)));
+            }
+            Inline::Link(text, url) => {
+                let href = escape_url(url);
+                output.push_str(&format!("<a href=\"{}\">{}</a>", href, inlines_to_html(text)));
+            }
+            Inline::Image(alt, url) => {
+                let (src, alt) = (escape_url(url), escape_attr(alt));
+                output.push_str(&format!("<img src=\"{}\" alt=\"{}\">", src, alt));
+            }
+            Inline::LineBreak => output.push_str("<br>\n"),
         }
     }
     output
 }
+
+/// Render a table, with the header in `<thead>` and alignments as inline styles.
+fn table_to_html(table: &Table, attr: &str, output: &mut impl Write) -> io::Result<()> {
+    let write_row = |tag: &str, cells: &[Vec<Inline>], output: &mut dyn Write| -> io::Result<()> {
+        output.write_all(b"<tr>\n")?;
+        for (cell, alignment) in cells.iter().zip(&table.alignments) {
+            let style = match alignment {
+                Alignment::None => "",
+                Alignment::Left => " style=\"text-align: left\"",
+                Alignment::Center => " style=\"text-align: center\"",
+                Alignment::Right => " style=\"text-align: right\"",
+            };
+            writeln!(output, "<{tag}{style}>{}</{tag}>", inlines_to_html(cell))?;
+        }
+        output.write_all(b"</tr>\n")
+    };
+
+    writeln!(output, "<table{attr}>\n<thead>")?;
+    write_row("th", &table.header, output)?;
+    output.write_all(b"</thead>\n")?;
+    if !table.rows.is_empty() {
+        output.write_all(b"<tbody>\n")?;
+        for row in &table.rows {
+            write_row("td", row, output)?;
+        }
+        output.write_all(b"</tbody>\n")?;
+    }
+    output.write_all(b"</table>\n")
+}
+
+/// Render one list item, with any nested blocks inside the `<li>`.
+fn list_item_to_html(item: &ListItem, source_lines: bool, output: &mut impl Write) -> io::Result<()> {
+    write!(output, "<li>{}", inlines_to_html(&item.text))?;
+    if !item.children.is_empty() {
+        output.write_all(b"\n")?;
+        blocks_to_html(&item.children, source_lines, output)?;
+    }
+    output.write_all(b"</li>\n")
+}
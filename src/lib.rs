@@ -10,7 +10,7 @@
 2. Fixes common formatting issues (extra spaces, random newlines, etc.).
 3. Detects headings, lists, code blocks, and more.
 4. User can override defaults via a configuration file (TOML or JSON).
-5. Exposes APIs to produce Markdown or HTML.
+5. Exposes APIs to produce Markdown, HTML or a JSON document tree.
 
 ## Usage
 
@@ -35,16 +35,25 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let html = formatter.format_to_html(input_text.as_bytes())?;
     println!("Converted HTML:\n{}", html);
 
+    // Stream Markdown to any `Write` as blocks are parsed
+    formatter.format_markdown_to(input_text.as_bytes(), std::io::stdout().lock())?;
+
     Ok(())
 }
 */
 
 pub mod config;
+pub mod detector;
 pub mod errors;
+pub mod escape;
 pub mod formatter;
 pub mod parser;
+pub mod renderer;
+pub mod schema;
 pub mod structures;
 
 // Re-export commonly used structs so callers can do use tform::{Config, Formatter};
 pub use config::Config;
-pub use formatter::Formatter;
\ No newline at end of file
+pub use detector::BlockDetector;
+pub use formatter::Formatter;
+pub use renderer::Renderer;
\ No newline at end of file
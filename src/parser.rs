@@ -2,12 +2,17 @@
 Parsing logic: reads input stream, cleans up text, and builds `Document`.
 */
 
-use std::io::BufRead;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{self, BufRead};
 use regex::Regex;
 
-use crate::config::Config;
+use crate::config::{Config, CustomPattern, RuleAction};
+use crate::detector::{priority, BlockDetector, DetectContext, Detection};
 use crate::errors::FormatterError;
-use crate::structures::{Block, Document};
+use crate::structures::{
+    Alignment, Block, Document, Inline, ListItem, ListStyle, Position, Span, Spanned, Table,
+};
 
 /// A parser that applies rules from `Config` to build a `Document`.
 pub struct Parser {
@@ -16,132 +21,2028 @@ pub struct Parser {
     re_extra_spaces: Regex,
     re_heading: Regex,
     re_bullet: Regex,
+    re_ordered: Regex,
+    re_fence: Regex,
+    re_delimiter_cell: Regex,
+    re_thematic_break: Regex,
+    re_setext_underline: Regex,
+    re_numbered_heading: Regex,
+    re_callout: Regex,
+    // User-defined rules from `Config::custom_patterns`, sorted by priority
+    rules: Vec<CompiledRule>,
+    // Known words (lowercase) for repairing words broken across lines
+    words: HashSet<String>,
+    // Built-in and user-registered detectors, highest priority first
+    detectors: Vec<Box<dyn BlockDetector>>,
+}
+
+/// A `CustomRule` with its regex compiled once up front.
+struct CompiledRule {
+    name: String,
+    regex: Regex,
+    action: RuleAction,
+    template: String,
+    level: u8,
+}
+
+/// An iterator over the top-level blocks of a document, created by
+/// [`Parser::parse_iter`].
+pub struct BlockIter<'a> {
+    parser: &'a Parser,
+    lines: LineCursor<'a>,
+    // Blocks completed by the last step but not yet yielded
+    pending: VecDeque<Spanned<Block>>,
+    done: bool,
+}
+
+impl Iterator for BlockIter<'_> {
+    type Item = Result<Spanned<Block>, FormatterError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() && !self.done {
+            let mut blocks = vec![];
+            match self.parser.parse_step(&mut self.lines, &mut blocks) {
+                Ok(more) => self.done = !more,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+            self.pending.extend(blocks);
+        }
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+/// A line of input, split into its indentation and content.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    /// Width of the leading whitespace in columns (tabs expanded to the next multiple of 4).
+    pub indent: usize,
+    /// The line without leading or trailing whitespace.
+    pub text: String,
+    /// The line without trailing whitespace, as written.
+    pub raw: String,
+    /// Where `text` starts in the input.
+    pub start: Position,
+    /// Where `text` ends in the input.
+    pub end: Position,
+    // Columns of indentation taken off for enclosing list items, which `raw` still has
+    dedented: usize,
+}
+
+impl Line {
+    /// Creates a line read from the input, given its line number and the
+    /// byte offset at which it starts.
+    pub(crate) fn new(raw: String, line: usize, offset: usize) -> Self {
+        let raw = raw.trim_end().to_string();
+        let leading = &raw[..raw.len() - raw.trim_start().len()];
+        let start = Position {
+            offset: offset + leading.len(),
+            line,
+            column: leading.chars().count() + 1,
+        };
+        Self::with_start(raw, start)
+    }
+
+    fn with_start(raw: String, start: Position) -> Self {
+        let text = raw.trim_start().to_string();
+        let indent = raw
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .fold(0, |col, c| if c == '\t' { col + 4 - col % 4 } else { col + 1 });
+        let end = Position {
+            offset: start.offset + text.len(),
+            line: start.line,
+            column: start.column + text.chars().count(),
+        };
+        Line {
+            indent,
+            text,
+            raw,
+            start,
+            end,
+            dedented: 0,
+        }
+    }
+
+    /// Takes `columns` of indentation off the line, without copying it.
+    fn dedent(mut self, columns: usize) -> Line {
+        if !self.is_blank() {
+            self.indent -= columns;
+            self.dedented += columns;
+        }
+        self
+    }
+
+    /// `raw` without the indentation taken off by `dedent`; what remains of
+    /// the indentation is then written as spaces.
+    fn dedented_raw(&self) -> Cow<'_, str> {
+        if self.dedented == 0 {
+            Cow::Borrowed(&self.raw)
+        } else {
+            Cow::Owned(format!("{}{}", " ".repeat(self.indent), self.text))
+        }
+    }
+
+    /// Derives a line with new content from this one, e.g. with a quote marker
+    /// or list indentation removed, keeping track of where it came from.
+    ///
+    /// When the new text is a suffix of this line's text its position is exact;
+    /// otherwise it is attributed to the whole of this line.
+    pub fn with_text(&self, raw: impl Into<String>) -> Line {
+        let raw = raw.into();
+        let text = raw.trim();
+        let start = match self.text.strip_suffix(text) {
+            Some(prefix) => Position {
+                offset: self.start.offset + prefix.len(),
+                line: self.start.line,
+                column: self.start.column + prefix.chars().count(),
+            },
+            None => self.start,
+        };
+        let mut line = Self::with_start(raw, start);
+        if !self.text.ends_with(line.text.as_str()) {
+            line.end = self.end;
+        }
+        line
+    }
+
+    /// The span of the line's text.
+    pub fn span(&self) -> Span {
+        Span {
+            start: self.start,
+            end: self.end,
+        }
+    }
+
+    /// Whether the line is empty or whitespace only.
+    pub fn is_blank(&self) -> bool {
+        self.text.is_empty()
+    }
+}
+
+/// Reads lines from a reader, recording where each one starts in the input.
+struct SourceLines<R> {
+    reader: R,
+    line: usize,
+    offset: usize,
+}
+
+impl<R: BufRead> Iterator for SourceLines<R> {
+    type Item = io::Result<Line>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut buf = String::new();
+        match self.reader.read_line(&mut buf) {
+            Ok(0) => None,
+            Ok(len) => {
+                self.line += 1;
+                let line = Line::new(buf, self.line, self.offset);
+                self.offset += len;
+                Some(Ok(line))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Input lines with lookahead, so block parsers can peek before consuming.
+pub(crate) struct LineCursor<'a> {
+    source: Box<dyn Iterator<Item = io::Result<Line>> + 'a>,
+    lookahead: VecDeque<Line>,
+    // End of the most recently consumed line, where the block being built ends
+    last_end: Position,
+    // How many container blocks the lines are nested in
+    depth: usize,
+}
+
+impl<'a> LineCursor<'a> {
+    pub(crate) fn new(source: impl Iterator<Item = io::Result<Line>> + 'a) -> Self {
+        LineCursor {
+            source: Box::new(source),
+            lookahead: VecDeque::new(),
+            last_end: Position::default(),
+            depth: 0,
+        }
+    }
+
+    fn from_reader<R: BufRead + 'a>(reader: R) -> Self {
+        Self::new(SourceLines {
+            reader,
+            line: 0,
+            offset: 0,
+        })
+    }
+
+    fn from_lines(lines: Vec<Line>, depth: usize) -> Self {
+        LineCursor {
+            depth,
+            ..Self::new(lines.into_iter().map(Ok))
+        }
+    }
+
+    /// Whether the lines are nested as deep as container blocks may go.
+    pub(crate) fn at_max_depth(&self) -> bool {
+        self.depth >= MAX_NESTING
+    }
+
+    /// Peeks `n` lines ahead without consuming anything.
+    pub(crate) fn peek_nth(&mut self, n: usize) -> Result<Option<&Line>, FormatterError> {
+        while self.lookahead.len() <= n {
+            match self.source.next() {
+                Some(line) => self.lookahead.push_back(line?),
+                None => return Ok(None),
+            }
+        }
+        Ok(self.lookahead.get(n))
+    }
+
+    fn peek(&mut self) -> Result<Option<&Line>, FormatterError> {
+        self.peek_nth(0)
+    }
+
+    pub(crate) fn next_line(&mut self) -> Result<Option<Line>, FormatterError> {
+        self.peek()?;
+        let line = self.lookahead.pop_front();
+        if let Some(line) = &line {
+            self.last_end = line.end;
+        }
+        Ok(line)
+    }
+}
+
+/// A list item marker found at the start of a line.
+struct ItemMarker {
+    // `None` for a bullet, otherwise the style and value of an ordered item
+    numbering: Option<(ListStyle, u32)>,
+    text: String,
+    // Where `text` starts in the line, past the marker and its spaces
+    offset: usize,
+    // The bullet character, or the delimiter of an ordered marker (`.` or `)`)
+    delimiter: char,
+}
+
+/// The three table layouts recognized in plain text.
+#[derive(Clone, Copy, PartialEq)]
+enum TableLayout {
+    /// `| a | b |` rows, optionally with a `|---|` delimiter row or `+---+` borders.
+    Pipe,
+    /// Tab-separated cells, as pasted from a spreadsheet.
+    Tabs,
+    /// Columns separated by runs of two or more spaces.
+    Aligned,
+}
+
+impl TableLayout {
+    fn accepts(self, text: &str) -> bool {
+        match self {
+            TableLayout::Pipe => text.contains('|') || is_grid_border(text),
+            TableLayout::Tabs => text.contains('\t'),
+            TableLayout::Aligned => text.contains("  ") && !text.contains('\t'),
+        }
+    }
+}
+
+/// What the custom rules decided to do with a line.
+enum RuleOutcome {
+    /// No terminal rule matched; continue with the (possibly rewritten) line.
+    Continue(String),
+    /// A rule produced a block for this line.
+    Block(Block),
+    /// A rule dropped the line.
+    Drop,
 }
 
 impl Parser {
     /// Creates a new `Parser` from a `Config`.
+    ///
+    /// Invalid custom patterns are left out and a word list that can't be read
+    /// is ignored; use `Parser::try_new` to report those instead.
     pub fn new(config: Config) -> Self {
+        let (rules, _) = compile_rules(&config.custom_patterns);
+        let (words, _) = load_words(&config);
+        Self::with_parts(config, rules, words)
+    }
+
+    /// Creates a new `Parser` from a `Config`, compiling its custom patterns
+    /// and loading its word list.
+    pub fn try_new(config: Config) -> Result<Self, FormatterError> {
+        let (rules, error) = compile_rules(&config.custom_patterns);
+        if let Some(error) = error {
+            return Err(error);
+        }
+        let (words, error) = load_words(&config);
+        if let Some(error) = error {
+            return Err(error);
+        }
+        Ok(Self::with_parts(config, rules, words))
+    }
+
+    fn with_parts(config: Config, rules: Vec<CompiledRule>, words: HashSet<String>) -> Self {
         Parser {
             config,
             re_extra_spaces: Regex::new(r"\s{2,}").unwrap(),
             re_heading: Regex::new(r"^(#+)\s*(.*)$").unwrap(), // e.g., "# Heading"
             re_bullet: Regex::new(r"^[-*+]\s+(.*)$").unwrap(),
+            // e.g., "1. step", "2) step", "a. step", "(iv) step"
+            re_ordered: Regex::new(r"^(?:\(([0-9]{1,9}|[a-zA-Z]+)\)|([0-9]{1,9}|[a-zA-Z]+)[.)])\s+(.*)$")
+                .unwrap(),
+            re_fence: Regex::new(r"^(`{3,}|~{3,})\s*(.*)$").unwrap(), // e.g., "```rust", "~~~~"
+            re_delimiter_cell: Regex::new(r"^:?-+:?$").unwrap(), // e.g., "---", ":--:"
+            // e.g., "---", "***", "____", "=====", "- - -"
+            re_thematic_break: Regex::new(r"^(?:(?:-[ \t]*){3,}|(?:\*[ \t]*){3,}|(?:_[ \t]*){3,}|(?:=[ \t]*){3,})$")
+                .unwrap(),
+            re_setext_underline: Regex::new(r"^(?:={3,}|-{3,})$").unwrap(),
+            re_numbered_heading: Regex::new(r"^(\d+(?:\.\d+)*)\.?\s+\p{Lu}").unwrap(), // e.g., "2.3 Results"
+            re_callout: Regex::new(r"^\[!([A-Za-z][\w-]*)\]$").unwrap(), // e.g., "[!NOTE]"
+            rules,
+            words,
+            detectors: builtin_detectors(),
         }
     }
 
+    /// Registers a detector, to run in order of its priority relative to the
+    /// built-in detectors (see `detector::priority`).
+    pub fn add_detector<D: BlockDetector + 'static>(&mut self, detector: D) {
+        let at = self
+            .detectors
+            .partition_point(|d| d.priority() >= detector.priority());
+        self.detectors.insert(at, Box::new(detector));
+    }
+
+    /// The configuration this parser was built with.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
     /// Parses text from a buffered reader into a `Document`.
     pub fn parse<R: BufRead>(&self, reader: R) -> Result<Document, FormatterError> {
-        let mut doc = Document::new();
+        let mut lines = LineCursor::from_reader(reader);
+        let blocks = self.parse_blocks(&mut lines)?;
+        Ok(Document { blocks })
+    }
+
+    /// Parses text from a buffered reader one top-level block at a time.
+    ///
+    /// Each block is yielded as soon as it is complete, so only the block
+    /// being built (plus a few lines of lookahead) is held in memory.
+    pub fn parse_iter<'a, R: BufRead + 'a>(&'a self, reader: R) -> BlockIter<'a> {
+        BlockIter {
+            parser: self,
+            lines: LineCursor::from_reader(reader),
+            pending: VecDeque::new(),
+            done: false,
+        }
+    }
 
+    /// Parses already-split lines (e.g. the body of a container block) into
+    /// blocks, nested one level deeper than `parent`.
+    pub(crate) fn parse_lines(
+        &self,
+        lines: Vec<Line>,
+        parent: &LineCursor,
+    ) -> Result<Vec<Spanned<Block>>, FormatterError> {
+        self.parse_blocks(&mut LineCursor::from_lines(lines, parent.depth + 1))
+    }
+
+    /// Parses blocks until the cursor is exhausted.
+    ///
+    /// Called recursively on the (dedented) bodies of list items and on the
+    /// contents of block quotes.
+    fn parse_blocks(&self, lines: &mut LineCursor) -> Result<Vec<Spanned<Block>>, FormatterError> {
+        let mut blocks = vec![];
+        while self.parse_step(lines, &mut blocks)? {}
+        Ok(blocks)
+    }
+
+    /// Reads lines until at least one block is complete, appending the
+    /// finished blocks to `blocks`. Returns `false` once the input is exhausted.
+    ///
+    /// A paragraph is always flushed before any other block is pushed, so no
+    /// paragraph text is left over between steps.
+    fn parse_step(
+        &self,
+        lines: &mut LineCursor,
+        blocks: &mut Vec<Spanned<Block>>,
+    ) -> Result<bool, FormatterError> {
+        let start = blocks.len();
         let mut paragraph_buffer = String::new();
-        let mut list_buffer: Vec<String> = vec![];
+        let mut paragraph_span = Span::default();
+
+        while blocks.len() == start {
+            let Some(line) = lines.next_line()? else {
+                // Flush any remaining paragraph
+                self.flush_paragraph(blocks, &mut paragraph_buffer, paragraph_span);
+                return Ok(blocks.len() > start);
+            };
+
+            // Remove extra spaces if config is set (indentation is kept separately)
+            let mut text = line.text.clone();
+            if self.config.remove_extra_spaces {
+                text = self.re_extra_spaces.replace_all(&text, " ").to_string();
+            }
+
+            // Offer the line to each detector in priority order; the first to claim it wins
+            let mut ctx = DetectContext {
+                parser: self,
+                lines: &mut *lines,
+                line: &line,
+                text,
+                in_paragraph: !paragraph_buffer.is_empty(),
+            };
+            let mut detection = None;
+            for detector in &self.detectors {
+                detection = detector.detect(&mut ctx)?;
+                if detection.is_some() {
+                    break;
+                }
+            }
+
+            let text = match detection {
+                Some(Detection::Block(block)) => {
+                    self.flush_paragraph(blocks, &mut paragraph_buffer, paragraph_span);
+                    // The block spans from this line to the last line its detector consumed
+                    let span = Span {
+                        start: line.start,
+                        end: lines.last_end,
+                    };
+                    blocks.push(Spanned::new(block, span));
+                    continue;
+                }
+                Some(Detection::Skip) => continue,
+                Some(Detection::Paragraph(text)) => text,
+                None => ctx.text,
+            };
 
-        // New for code blocks:
-        let mut in_code_block = false;
+            // If line is blank, flush the paragraph
+            if text.is_empty() {
+                self.flush_paragraph(blocks, &mut paragraph_buffer, paragraph_span);
+                continue;
+            }
+            if paragraph_buffer.is_empty() {
+                paragraph_span.start = line.start;
+            }
+            paragraph_span.end = line.end;
+            if paragraph_buffer.ends_with('\\') {
+                // Accumulate paragraph text; a trailing backslash keeps the line break
+                paragraph_buffer.push('\n');
+                paragraph_buffer.push_str(&text);
+            } else if paragraph_buffer.is_empty() {
+                paragraph_buffer.push_str(&text);
+            } else {
+                self.join_wrapped_line(&mut paragraph_buffer, &text);
+            }
+
+            // An underline below a paragraph promotes all of it, as in CommonMark;
+            // a heading can't hold a hard line break, so such a paragraph is kept
+            if !paragraph_buffer.contains('\n') {
+                if let Some(level) = self.setext_level(lines)? {
+                    lines.next_line()?;
+                    let heading = Block::Heading(level, parse_inlines(paragraph_buffer.trim()));
+                    let span = Span {
+                        start: paragraph_span.start,
+                        end: lines.last_end,
+                    };
+                    blocks.push(Spanned::new(heading, span));
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Matches an opening code fence, returning the fence (e.g. "````") and info string.
+    ///
+    /// Follows CommonMark: at most three spaces of indentation, three or more
+    /// backticks or tildes, and no backticks in the info string of a backtick fence.
+    fn match_fence(&self, line: &Line) -> Option<(String, Option<String>)> {
+        if line.indent > 3 {
+            return None;
+        }
+        let caps = self.re_fence.captures(&line.text)?;
+        let fence = caps[1].to_string();
+        let info = caps[2].trim();
+        if fence.starts_with('`') && info.contains('`') {
+            return None;
+        }
+        let info = (!info.is_empty()).then(|| info.to_string());
+        Some((fence, info))
+    }
+
+    /// Parses a fenced code block whose opening fence line has just been consumed.
+    ///
+    /// The block ends at a fence of the same character that is at least as long
+    /// (or at the end of input). Content lines lose up to as much indentation as
+    /// the opening fence had; everything else is kept verbatim.
+    fn parse_fenced_code(
+        &self,
+        lines: &mut LineCursor,
+        opening: &Line,
+        (fence, info): (String, Option<String>),
+    ) -> Result<Block, FormatterError> {
+        let fence_char = fence.chars().next().unwrap_or('`');
         let mut code_block_buffer: Vec<String> = vec![];
 
-        for line_result in reader.lines() {
-            let mut line = line_result?;
-            line = line.trim_end().to_string(); // remove trailing whitespace
+        while let Some(code_line) = lines.next_line()? {
+            let text = &code_line.text;
+            let is_closing = code_line.indent <= 3
+                && text.len() >= fence.len()
+                && text.chars().all(|c| c == fence_char);
+            if is_closing {
+                break;
+            }
+            code_block_buffer.push(strip_indent(&code_line.dedented_raw(), opening.indent).to_string());
+        }
+
+        Ok(Block::CodeBlock(info, code_block_buffer.join("\n")))
+    }
 
-            // Check for triple-backtick line (open/close code block)
-            if line.trim() == "```" {
-                if in_code_block {
-                    // Closing a code block
-                    in_code_block = false;
+    /// Whether a line is indented far enough to be (part of) an indented code block.
+    fn is_indented_code(&self, line: &Line) -> bool {
+        self.config.detect_indented_code
+            && !line.is_blank()
+            && line.indent >= self.config.indented_code_width.max(1)
+    }
 
-                    // Push the accumulated code as a CodeBlock
-                    let code_text = code_block_buffer.join("\n");
-                    doc.blocks.push(Block::CodeBlock(code_text));
-                    code_block_buffer.clear();
-                } else {
-                    // Entering a code block
-                    in_code_block = true;
+    /// Parses an indented code block starting at `first`.
+    ///
+    /// Blank lines are kept only when more indented code follows them. The
+    /// threshold indentation is removed; any deeper whitespace is preserved.
+    fn parse_indented_code(&self, lines: &mut LineCursor, first: Line) -> Result<Block, FormatterError> {
+        let width = self.config.indented_code_width.max(1);
+        let mut code_block_buffer = vec![strip_indent(&first.dedented_raw(), width).to_string()];
 
-                    // Flush any existing paragraph or list
-                    self.flush_paragraph(&mut doc, &mut paragraph_buffer);
-                    self.flush_list(&mut doc, &mut list_buffer);
+        loop {
+            let mut n = 0;
+            while lines.peek_nth(n)?.is_some_and(Line::is_blank) {
+                n += 1;
+            }
+            if !lines.peek_nth(n)?.is_some_and(|l| self.is_indented_code(l)) {
+                break;
+            }
+            for _ in 0..=n {
+                if let Some(code_line) = lines.next_line()? {
+                    code_block_buffer.push(strip_indent(&code_line.dedented_raw(), width).to_string());
                 }
+            }
+        }
+
+        Ok(Block::CodeBlock(None, code_block_buffer.join("\n")))
+    }
+
+    /// Peeks at the next line for a setext underline, returning the heading level
+    /// it implies: 1 for `===`, 2 for `---`.
+    fn setext_level(&self, lines: &mut LineCursor) -> Result<Option<u8>, FormatterError> {
+        if !(self.config.detect_headings && self.config.detect_setext_headings) {
+            return Ok(None);
+        }
+        let level = match lines.peek()? {
+            Some(next) if next.indent <= 3 && self.re_setext_underline.is_match(&next.text) => {
+                Some(if next.text.starts_with('=') { 1 } else { 2 })
+            }
+            _ => None,
+        };
+        Ok(level)
+    }
+
+    /// Guesses whether an unmarked line is a heading, using the opt-in heuristics.
+    ///
+    /// Only short standalone lines qualify: not continuing a paragraph,
+    /// followed by a blank line (or the end of input), and without terminal
+    /// punctuation. Numbered lines get one level per number component ("2.3"
+    /// is level 2); ALL CAPS lines are level 1 and Title Case lines level 2.
+    /// Lines such as "1. Introduction" never get here: the list detector runs
+    /// first and reads them as ordered list items.
+    fn guess_heading_level(
+        &self,
+        text: &str,
+        in_paragraph: bool,
+        lines: &mut LineCursor,
+    ) -> Result<Option<u8>, FormatterError> {
+        let config = &self.config;
+        let enabled = config.detect_all_caps_headings
+            || config.detect_title_case_headings
+            || config.detect_numbered_headings;
+        if !enabled || !config.detect_headings || in_paragraph {
+            return Ok(None);
+        }
+
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let short = words.len() <= MAX_HEADING_WORDS && text.chars().count() <= MAX_HEADING_CHARS;
+        if !short || text.ends_with(['.', ',', ';', ':', '!', '?']) {
+            return Ok(None);
+        }
+        if !lines.peek()?.is_none_or(Line::is_blank) {
+            return Ok(None);
+        }
+
+        if config.detect_numbered_headings {
+            if let Some(caps) = self.re_numbered_heading.captures(text) {
+                let depth = caps[1].split('.').count();
+                return Ok(Some(depth.min(6) as u8));
+            }
+        }
+        if config.detect_all_caps_headings && is_all_caps(text) {
+            return Ok(Some(1));
+        }
+        if config.detect_title_case_headings && is_title_case(&words) {
+            return Ok(Some(2));
+        }
+        Ok(None)
+    }
+
+    /// Whether a line is a thematic break such as `---`, `***` or `- - -`.
+    fn is_thematic_break(&self, indent: usize, text: &str) -> bool {
+        indent <= 3 && self.re_thematic_break.is_match(text)
+    }
+
+    /// Whether a line starts with a `>` quote marker.
+    fn is_quote(&self, line: &Line) -> bool {
+        self.config.detect_quotes && line.indent <= 3 && line.text.starts_with('>')
+    }
+
+    /// Parses a block quote starting at `first`.
+    ///
+    /// Consecutive quoted lines are collected, one level of `>` (plus one
+    /// following space) is stripped, and the remainder is parsed recursively,
+    /// so nested `>>` quoting becomes nested quotes. Past `MAX_NESTING` levels,
+    /// the remaining `>` markers are kept as text.
+    ///
+    /// A quote opening with an alert marker such as `[!NOTE]` and holding at
+    /// most one paragraph is a callout, as written by the Markdown renderer.
+    fn parse_quote(&self, lines: &mut LineCursor, first: Line) -> Result<Block, FormatterError> {
+        let mut quoted = vec![first.with_text(strip_quote_marker(&first.text))];
+        while lines.peek()?.is_some_and(|l| self.is_quote(l)) {
+            if let Some(line) = lines.next_line()? {
+                quoted.push(line.with_text(strip_quote_marker(&line.text)));
+            }
+        }
+
+        if let Some(callout) = self.parse_callout(&quoted) {
+            return Ok(callout);
+        }
+        Ok(Block::Quote(self.parse_lines(quoted, lines)?))
+    }
+
+    /// Reads quoted lines as a callout: an alert marker line followed by
+    /// paragraph text.
+    ///
+    /// The text is joined like a paragraph but not offered to the block
+    /// detectors, so a callout made by a custom rule doesn't match that rule
+    /// again when its output is parsed.
+    fn parse_callout(&self, quoted: &[Line]) -> Option<Block> {
+        let (marker, body) = quoted.split_first()?;
+        let caps = self.re_callout.captures(&marker.text)?;
+        if body.iter().any(|l| l.is_blank() || l.text.starts_with('>')) {
+            return None;
+        }
+
+        let mut buffer = String::new();
+        for line in body {
+            let mut text = line.text.clone();
+            if self.config.remove_extra_spaces {
+                text = self.re_extra_spaces.replace_all(&text, " ").to_string();
+            }
+            if buffer.ends_with('\\') {
+                buffer.push('\n');
+                buffer.push_str(&text);
+            } else if buffer.is_empty() {
+                buffer.push_str(&text);
+            } else {
+                self.join_wrapped_line(&mut buffer, &text);
+            }
+        }
+        Some(Block::Callout(caps[1].to_lowercase(), parse_inlines(buffer.trim())))
+    }
+
+    /// Tries to parse a table starting at `first`, consuming its rows on success.
+    ///
+    /// The first row is the header. Only pipe tables with a delimiter row may
+    /// interrupt a paragraph; the other layouts are heuristics and need at
+    /// least one body row.
+    fn try_table(
+        &self,
+        lines: &mut LineCursor,
+        first: &Line,
+        in_paragraph: bool,
+    ) -> Result<Option<Block>, FormatterError> {
+        if !self.config.detect_tables || first.indent > 3 {
+            return Ok(None);
+        }
+        let Some(layout) = [TableLayout::Pipe, TableLayout::Tabs, TableLayout::Aligned]
+            .into_iter()
+            .find(|layout| layout.accepts(&first.text))
+        else {
+            return Ok(None);
+        };
+        // The heuristic layouts yield to the detectors below them, so aligned
+        // list items or headings keep their meaning
+        let heuristic = layout != TableLayout::Pipe;
+        if heuristic && self.claimed_elsewhere(first) {
+            return Ok(None);
+        }
+
+        // Inside a paragraph only a delimiter row right below can start a table,
+        // so check it before collecting rows that paragraph lines would rescan
+        if in_paragraph {
+            if layout != TableLayout::Pipe {
+                return Ok(None);
+            }
+            let delimiter = lines.peek_nth(0)?.filter(|next| {
+                next.indent <= 3
+                    && split_pipe_row(next.text.trim())
+                        .iter()
+                        .all(|cell| self.re_delimiter_cell.is_match(cell))
+            });
+            if delimiter.is_none() {
+                return Ok(None);
+            }
+        }
+
+        // Collect the run of candidate rows without consuming them yet
+        let mut rows = vec![first.dedented_raw().into_owned()];
+        while let Some(next) = lines.peek_nth(rows.len() - 1)? {
+            if next.is_blank()
+                || next.indent > 3
+                || !layout.accepts(&next.text)
+                || (heuristic && self.claimed_elsewhere(next))
+            {
+                break;
+            }
+            rows.push(next.dedented_raw().into_owned());
+        }
+
+        let table = match layout {
+            TableLayout::Pipe => self.pipe_table(&rows, in_paragraph),
+            TableLayout::Tabs => self.tab_table(&rows),
+            TableLayout::Aligned => self.aligned_table(&rows),
+        };
+
+        if table.is_some() {
+            for _ in 1..rows.len() {
+                lines.next_line()?;
+            }
+        }
+        Ok(table.map(Block::Table))
+    }
+
+    /// Whether a list, heading, quote or custom rule would claim `line`.
+    fn claimed_elsewhere(&self, line: &Line) -> bool {
+        (self.config.detect_lists && self.match_item(&line.text, None).is_some())
+            || (self.config.detect_headings && self.re_heading.is_match(&line.text))
+            || self.is_quote(line)
+            || self
+                .rules
+                .iter()
+                .any(|rule| rule.action != RuleAction::Replace && rule.regex.is_match(&line.text))
+    }
+
+    /// Builds a pipe (or grid) table from its rows.
+    fn pipe_table(&self, rows: &[String], in_paragraph: bool) -> Option<Table> {
+        let mut cells: Vec<Vec<String>> = vec![];
+        let mut header_border = false;
+        for row in rows {
+            let row = row.trim();
+            if is_grid_border(row) {
+                // A border right below the first row marks it as the header
+                header_border |= cells.len() == 1;
                 continue;
             }
+            cells.push(split_pipe_row(row));
+        }
 
-            // If we're inside a code block, collect lines verbatim
-            if in_code_block {
-                code_block_buffer.push(line);
+        // GFM style: the second row is a delimiter row such as |---|:--:|
+        let delimiter = cells
+            .get(1)
+            .filter(|row| row.iter().all(|cell| self.re_delimiter_cell.is_match(cell)))
+            .map(|row| row.iter().map(|cell| cell_alignment(cell)).collect::<Vec<_>>());
+
+        match delimiter {
+            Some(alignments) if alignments.len() == cells[0].len() => {
+                cells.remove(1);
+                Some(self.build_table(cells, Some(alignments)))
+            }
+            Some(_) => None,
+            None if in_paragraph => None,
+            None => {
+                // Without a delimiter row, insist on a regular grid of |-delimited rows
+                let columns = cells.first()?.len();
+                let regular = cells.len() >= 2
+                    && columns >= 2
+                    && cells.iter().all(|row| row.len() == columns)
+                    && rows.iter().all(|r| r.trim_start().starts_with(['|', '+']));
+                (regular || (header_border && columns >= 2)).then(|| self.build_table(cells, None))
+            }
+        }
+    }
+
+    /// Builds a table from tab-separated rows.
+    fn tab_table(&self, rows: &[String]) -> Option<Table> {
+        let cells: Vec<Vec<String>> = rows
+            .iter()
+            .map(|row| row.trim().split('\t').map(|c| c.trim().to_string()).collect())
+            .collect();
+
+        let columns = cells.first()?.len();
+        (rows.len() >= 2 && columns >= 2).then(|| self.build_table(cells, None))
+    }
+
+    /// Builds a table from columns aligned with spaces.
+    ///
+    /// Columns are found from "gutters": runs of at least two character
+    /// positions that are blank on every row. Each row must have text in at
+    /// least two columns, and the header in all of them. A cell that ends a
+    /// sentence before a gutter means prose typed with two spaces after its
+    /// full stops, not a table.
+    fn aligned_table(&self, rows: &[String]) -> Option<Table> {
+        if rows.len() < 2 {
+            return None;
+        }
+        let chars: Vec<Vec<char>> = rows.iter().map(|r| r.chars().collect()).collect();
+        let width = chars.iter().map(Vec::len).max()?;
+        let blank_at = |p: usize| chars.iter().all(|row| row.get(p).is_none_or(|c| *c == ' '));
+
+        // Split the positions into columns at gutters two or more wide
+        let mut columns: Vec<(usize, usize)> = vec![];
+        let mut p = 0;
+        while p < width {
+            if blank_at(p) {
+                p += 1;
                 continue;
             }
+            let start = p;
+            while p < width && !(blank_at(p) && (p + 1 >= width || blank_at(p + 1))) {
+                p += 1;
+            }
+            columns.push((start, p));
+        }
+        if columns.len() < 2 {
+            return None;
+        }
 
-            // Remove extra spaces if config is set
+        let mut cells: Vec<Vec<String>> = chars
+            .iter()
+            .map(|row| {
+                columns
+                    .iter()
+                    .map(|&(start, end)| {
+                        let end = end.min(row.len());
+                        let cell: String = row.get(start..end).unwrap_or_default().iter().collect();
+                        cell.trim().to_string()
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let filled = |row: &Vec<String>| row.iter().filter(|c| !c.is_empty()).count();
+        if filled(&cells[0]) != columns.len() || cells.iter().any(|row| filled(row) < 2) {
+            return None;
+        }
+        let ends_sentence = |cell: &String| cell.contains(' ') && cell.ends_with(['.', '!', '?']);
+        if cells.iter().any(|row| row[..row.len() - 1].iter().any(ends_sentence)) {
+            return None;
+        }
+
+        // An underline of dashes below the header is a separator, not data
+        if cells[1].iter().all(|c| c.chars().all(|ch| ch == '-' || ch == '=')) {
+            cells.remove(1);
+            if cells.len() < 2 {
+                return None;
+            }
+        }
+        Some(self.build_table(cells, None))
+    }
+
+    /// Turns split cell text into a `Table`, padding short rows.
+    ///
+    /// Without explicit alignments, columns whose body cells are all numbers
+    /// are right-aligned.
+    fn build_table(&self, mut cells: Vec<Vec<String>>, alignments: Option<Vec<Alignment>>) -> Table {
+        let header = cells.remove(0);
+        let columns = header.len();
+        for row in &mut cells {
+            row.resize(columns, String::new());
+        }
+
+        let alignments = alignments.unwrap_or_else(|| {
+            (0..columns)
+                .map(|col| {
+                    let mut values = cells
+                        .iter()
+                        .map(|row| row[col].as_str())
+                        .filter(|c| !c.is_empty())
+                        .peekable();
+                    if values.peek().is_some() && values.all(is_numeric) {
+                        Alignment::Right
+                    } else {
+                        Alignment::None
+                    }
+                })
+                .collect()
+        });
+
+        let to_inlines = |text: &str| {
             if self.config.remove_extra_spaces {
-                line = self.re_extra_spaces.replace_all(&line, " ").to_string();
+                parse_inlines(&self.re_extra_spaces.replace_all(text, " "))
+            } else {
+                parse_inlines(text)
             }
+        };
+        Table {
+            alignments,
+            header: header.iter().map(|c| to_inlines(c)).collect(),
+            rows: cells
+                .iter()
+                .map(|row| row.iter().map(|c| to_inlines(c)).collect())
+                .collect(),
+        }
+    }
 
-            // Check for headings if enabled
-            if self.config.detect_headings {
-                if let Some(caps) = self.re_heading.captures(&line) {
-                    // Flush paragraph/list first
-                    self.flush_paragraph(&mut doc, &mut paragraph_buffer);
-                    self.flush_list(&mut doc, &mut list_buffer);
+    /// Parses a list whose first item (at column `indent`) has just been consumed.
+    ///
+    /// Every following line indented deeper than an item belongs to that item;
+    /// those lines are dedented to their common indentation and parsed
+    /// recursively, which yields nested lists and paragraphs. Tabs count as
+    /// four columns, so mixed tab/space indentation nests consistently.
+    fn parse_list(
+        &self,
+        lines: &mut LineCursor,
+        first_line: &Line,
+        first: ItemMarker,
+    ) -> Result<Block, FormatterError> {
+        let numbering = first.numbering;
+        let indent = first_line.indent;
+        let mut items = vec![];
+        let mut marker = first;
+        let mut item_line = first_line.clone();
+        let mut item_indent = indent;
 
-                    let hashes = &caps[1];
-                    let text = caps[2].trim();
-                    let level = hashes.len() as u8;
-                    doc.blocks.push(Block::Heading(level, text.to_string()));
+        loop {
+            // Gather the lines that belong to the current item
+            let mut body: Vec<Line> = vec![];
+            while let Some(next) = lines.peek()? {
+                if next.is_blank() {
+                    // Blank lines stay inside the item only if indented content
+                    // follows; take the whole run at once rather than rescanning it
+                    let mut n = 1;
+                    while lines.peek_nth(n)?.is_some_and(Line::is_blank) {
+                        n += 1;
+                    }
+                    if lines.peek_nth(n)?.is_none_or(|l| l.indent <= item_indent) {
+                        break;
+                    }
+                    for _ in 0..n {
+                        body.extend(lines.next_line()?);
+                    }
                     continue;
                 }
+                if next.indent <= item_indent {
+                    break;
+                }
+                body.extend(lines.next_line()?);
             }
 
-            // Check for lists if enabled
-            if self.config.detect_lists {
-                if let Some(caps) = self.re_bullet.captures(&line) {
-                    // Flush paragraph first
-                    self.flush_paragraph(&mut doc, &mut paragraph_buffer);
-                    list_buffer.push(caps[1].to_string());
-                    continue;
-                } else if !list_buffer.is_empty() {
-                    // If we were collecting a list and found a non-list line
-                    self.flush_list(&mut doc, &mut list_buffer);
+            // The column the item text starts at, past the marker
+            let content = item_line.indent + marker.offset;
+            items.push(self.parse_item(lines, item_line.with_text(marker.text), content, body)?);
+
+            // Skip blank lines between items, then continue only with a sibling item
+            let mut n = 0;
+            while lines.peek_nth(n)?.is_some_and(Line::is_blank) {
+                n += 1;
+            }
+            let current_style = numbering.map(|(style, _)| style);
+            let sibling = match lines.peek_nth(n)? {
+                Some(next) if next.indent > item_indent.max(indent) => None,
+                Some(next) if self.is_thematic_break(next.indent, &next.text) => None,
+                // After a blank line, other markers start a new list (as in CommonMark)
+                Some(next) => self
+                    .match_item(&next.text, current_style)
+                    .filter(|m| m.numbering.map(|(s, _)| s) == current_style)
+                    .filter(|m| n == 0 || m.delimiter == marker.delimiter)
+                    .map(|m| (next.indent, m)),
+                _ => None,
+            };
+            let Some((next_indent, next_marker)) = sibling else {
+                break;
+            };
+            for _ in 0..=n {
+                if let Some(line) = lines.next_line()? {
+                    item_line = line;
                 }
             }
+            item_indent = next_indent;
+            marker = next_marker;
+        }
 
-            // If line is blank, flush the paragraph
-            if line.trim().is_empty() {
-                self.flush_paragraph(&mut doc, &mut paragraph_buffer);
-            } else {
-                // Accumulate paragraph text
-                if !paragraph_buffer.is_empty() {
-                    paragraph_buffer.push(' ');
+        Ok(match numbering {
+            Some((style, start)) => Block::OrderedList(style, start, items),
+            None => Block::List(items),
+        })
+    }
+
+    /// Builds a list item from its first line and the (still indented) lines below it.
+    ///
+    /// `content` is the column the item text starts at, and `lines` the cursor
+    /// the list is read from.
+    fn parse_item(
+        &self,
+        lines: &LineCursor,
+        first: Line,
+        content: usize,
+        body: Vec<Line>,
+    ) -> Result<ListItem, FormatterError> {
+        // Dedent the body to its common indentation, so nesting is relative to this item,
+        // but no further than the item text: deeper lines belong to blocks inside it.
+        // Only the indent changes, so nesting doesn't copy every line again per level
+        let base = body
+            .iter()
+            .filter(|l| !l.is_blank())
+            .map(|l| l.indent)
+            .min()
+            .unwrap_or(0)
+            .min(content);
+        let dedented = body.into_iter().map(|l| l.dedent(base));
+
+        let source = std::iter::once(first).chain(dedented).collect();
+        let mut children = self.parse_lines(source, lines)?;
+
+        // The leading paragraph is the item's own text; everything else is nested content
+        let text = match children.first().map(|b| &b.node) {
+            Some(Block::Paragraph(_)) => match children.remove(0).node {
+                Block::Paragraph(text) => text,
+                _ => unreachable!(),
+            },
+            _ => vec![],
+        };
+        Ok(ListItem { text, children })
+    }
+
+    /// Runs the custom rules over a line, in priority order.
+    ///
+    /// `Replace` rules rewrite the line and let later rules see the result; the
+    /// first matching `Callout`, `Heading` or `Drop` rule claims the line.
+    fn apply_rules(&self, mut line: String) -> RuleOutcome {
+        if line.trim().is_empty() {
+            return RuleOutcome::Continue(line);
+        }
+
+        for rule in &self.rules {
+            let Some(caps) = rule.regex.captures(&line) else {
+                continue;
+            };
+
+            // The first capture group (if any) is the interesting part of the line
+            let text = caps
+                .get(1)
+                .map_or(line.as_str(), |m| m.as_str())
+                .trim()
+                .to_string();
+
+            match rule.action {
+                RuleAction::Replace => {
+                    line = rule
+                        .regex
+                        .replace_all(&line, rule.template.as_str())
+                        .to_string();
+                }
+                RuleAction::Callout => {
+                    let text = parse_inlines(&text);
+                    return RuleOutcome::Block(Block::Callout(rule.name.clone(), text));
                 }
-                paragraph_buffer.push_str(&line);
+                RuleAction::Heading => {
+                    return RuleOutcome::Block(Block::Heading(rule.level, parse_inlines(&text)));
+                }
+                RuleAction::Drop => return RuleOutcome::Drop,
             }
         }
 
-        // Flush any remaining paragraph or list
-        self.flush_paragraph(&mut doc, &mut paragraph_buffer);
-        self.flush_list(&mut doc, &mut list_buffer);
+        RuleOutcome::Continue(line)
+    }
+
+    /// Appends a wrapped line to a paragraph, repairing a word broken at the wrap.
+    ///
+    /// With a trailing hyphen ("exam-" + "ple"), the fragments are joined
+    /// unless they form a real compound: both halves are known words (and the
+    /// joined word is not), the first half is a compound prefix like "self", or
+    /// the second half starts with a capital or digit ("Jean-" + "Paul", "COVID-" + "19").
+    /// Without a hyphen, fragments are joined only when the joined word is
+    /// known and the fragments are not both words ("wit" + "hin", but not "in" + "to").
+    fn join_wrapped_line(&self, buffer: &mut String, line: &str) {
+        let left = trailing_word(buffer.strip_suffix('-').unwrap_or(buffer));
+        let right = leading_word(line);
+        let starts_lowercase = right.chars().next().is_some_and(char::is_lowercase);
+        let hyphenated = buffer.ends_with('-') && self.config.repair_hyphenation;
 
-        // If file ended while still in a code block (no closing ```), decide how to handle it:
-        if in_code_block {
-            let code_text = code_block_buffer.join("\n");
-            doc.blocks.push(Block::CodeBlock(code_text));
+        if !left.is_empty() && hyphenated && line.starts_with(|c: char| c.is_uppercase() || c.is_ascii_digit()) {
+            buffer.push_str(line);
+            return;
         }
 
-        Ok(doc)
+        if !left.is_empty() && starts_lowercase {
+            let joined = format!("{}{}", left, right).to_lowercase();
+            let known = |word: &str| self.words.contains(&word.to_lowercase());
+
+            if hyphenated {
+                let compound = !known(&joined)
+                    && ((known(left) && known(right)) || COMPOUND_PREFIXES.contains(&left.to_lowercase().as_str()));
+                if !compound {
+                    buffer.pop();
+                }
+                buffer.push_str(line);
+                return;
+            }
+
+            // Either fragment being a word ("to" + "ward") is more likely a real line break
+            if self.config.repair_split_words && known(&joined) && !known(left) && !known(right) {
+                buffer.push_str(line);
+                return;
+            }
+        }
+
+        buffer.push(' ');
+        buffer.push_str(line);
     }
 
     /// Helper to finalize and store a paragraph block
-    fn flush_paragraph(&self, doc: &mut Document, buffer: &mut String) {
+    fn flush_paragraph(&self, blocks: &mut Vec<Spanned<Block>>, buffer: &mut String, span: Span) {
         if !buffer.trim().is_empty() {
-            doc.blocks.push(Block::Paragraph(buffer.trim().to_string()));
+            blocks.push(Spanned::new(Block::Paragraph(parse_inlines(buffer.trim())), span));
         }
         buffer.clear();
     }
 
-    /// Helper to finalize and store a list block
-    fn flush_list(&self, doc: &mut Document, list_buffer: &mut Vec<String>) {
-        if !list_buffer.is_empty() {
-            doc.blocks.push(Block::List(list_buffer.clone()));
+    /// Matches a bullet or ordered list item, returning its marker and text.
+    ///
+    /// Single letters are ambiguous ("i." may be alphabetic or roman), so the
+    /// style of the list being continued decides; a fresh "i."/"I." starts a roman list.
+    fn match_item(&self, line: &str, current: Option<ListStyle>) -> Option<ItemMarker> {
+        if let Some(caps) = self.re_bullet.captures(line) {
+            return Some(ItemMarker {
+                numbering: None,
+                text: caps[1].to_string(),
+                offset: caps.get(1)?.start(),
+                delimiter: line.chars().next()?,
+            });
+        }
+
+        let caps = self.re_ordered.captures(line)?;
+        let (marker, delimiter) = match caps.get(1) {
+            Some(marker) => (marker.as_str(), ')'),
+            None => {
+                let marker = caps.get(2)?;
+                (marker.as_str(), line[marker.end()..].chars().next()?)
+            }
+        };
+        let text = caps[3].trim().to_string();
+        if text.is_empty() {
+            return None;
+        }
+
+        let numbering = parse_enumerator(marker, current)?;
+        Some(ItemMarker {
+            numbering: Some(numbering),
+            text,
+            offset: caps.get(3)?.start(),
+            delimiter,
+        })
+    }
+}
+
+/// The built-in detectors, highest priority first.
+fn builtin_detectors() -> Vec<Box<dyn BlockDetector>> {
+    vec![
+        Box::new(FencedCodeDetector),
+        Box::new(IndentedCodeDetector),
+        Box::new(QuoteDetector),
+        Box::new(TableDetector),
+        Box::new(CustomRuleDetector),
+        Box::new(HeadingDetector),
+        Box::new(ThematicBreakDetector),
+        Box::new(ListDetector),
+        Box::new(SetextHeadingDetector),
+        Box::new(HeadingHeuristicsDetector),
+    ]
+}
+
+/// Code fences (``` or ~~~, optionally followed by an info string).
+struct FencedCodeDetector;
+
+impl BlockDetector for FencedCodeDetector {
+    fn priority(&self) -> i32 {
+        priority::FENCED_CODE
+    }
+
+    fn detect(&self, ctx: &mut DetectContext) -> Result<Option<Detection>, FormatterError> {
+        let Some(fence) = ctx.parser.match_fence(ctx.line) else {
+            return Ok(None);
+        };
+        let block = ctx.parser.parse_fenced_code(ctx.lines, ctx.line, fence)?;
+        Ok(Some(Detection::Block(block)))
+    }
+}
+
+/// Indented code; it can't interrupt a paragraph, or start one whose first
+/// line is indented, so wrapped text stays intact.
+struct IndentedCodeDetector;
+
+impl BlockDetector for IndentedCodeDetector {
+    fn priority(&self) -> i32 {
+        priority::INDENTED_CODE
+    }
+
+    fn detect(&self, ctx: &mut DetectContext) -> Result<Option<Detection>, FormatterError> {
+        if ctx.in_paragraph || !ctx.parser.is_indented_code(ctx.line) {
+            return Ok(None);
         }
-        list_buffer.clear();
+        // An indented first line followed straight away by wrapped text is a
+        // paragraph indent, not code
+        if ctx.lines.peek()?.is_some_and(|next| !next.is_blank() && !ctx.parser.is_indented_code(next)) {
+            return Ok(None);
+        }
+        let block = ctx.parser.parse_indented_code(ctx.lines, ctx.line.clone())?;
+        Ok(Some(Detection::Block(block)))
+    }
+}
+
+/// Quoted lines ("> text", or ">> text" from email threads).
+struct QuoteDetector;
+
+impl BlockDetector for QuoteDetector {
+    fn priority(&self) -> i32 {
+        priority::QUOTE
+    }
+
+    fn detect(&self, ctx: &mut DetectContext) -> Result<Option<Detection>, FormatterError> {
+        if !ctx.parser.is_quote(ctx.line) || ctx.lines.at_max_depth() {
+            return Ok(None);
+        }
+        let block = ctx.parser.parse_quote(ctx.lines, ctx.line.clone())?;
+        Ok(Some(Detection::Block(block)))
     }
 }
+
+/// Tables; they look at the line as written, since spaces may align columns.
+struct TableDetector;
+
+impl BlockDetector for TableDetector {
+    fn priority(&self) -> i32 {
+        priority::TABLE
+    }
+
+    fn detect(&self, ctx: &mut DetectContext) -> Result<Option<Detection>, FormatterError> {
+        let table = ctx.parser.try_table(ctx.lines, ctx.line, ctx.in_paragraph)?;
+        Ok(table.map(Detection::Block))
+    }
+}
+
+/// User-defined rules from `Config::custom_patterns`.
+struct CustomRuleDetector;
+
+impl BlockDetector for CustomRuleDetector {
+    fn priority(&self) -> i32 {
+        priority::CUSTOM_RULES
+    }
+
+    fn detect(&self, ctx: &mut DetectContext) -> Result<Option<Detection>, FormatterError> {
+        let text = std::mem::take(&mut ctx.text);
+        Ok(match ctx.parser.apply_rules(text) {
+            RuleOutcome::Continue(rewritten) => {
+                ctx.text = rewritten;
+                None
+            }
+            RuleOutcome::Block(block) => Some(Detection::Block(block)),
+            RuleOutcome::Drop => Some(Detection::Skip),
+        })
+    }
+}
+
+/// ATX headings ("# Heading").
+struct HeadingDetector;
+
+impl BlockDetector for HeadingDetector {
+    fn priority(&self) -> i32 {
+        priority::HEADING
+    }
+
+    fn detect(&self, ctx: &mut DetectContext) -> Result<Option<Detection>, FormatterError> {
+        if !ctx.parser.config.detect_headings {
+            return Ok(None);
+        }
+        let Some(caps) = ctx.parser.re_heading.captures(&ctx.text) else {
+            return Ok(None);
+        };
+        let level = caps[1].len() as u8;
+        let text = caps[2].trim();
+        Ok(Some(Detection::Block(Block::Heading(level, parse_inlines(text)))))
+    }
+}
+
+/// Thematic breaks; checked before lists, since "- - -" also looks like a bullet.
+struct ThematicBreakDetector;
+
+impl BlockDetector for ThematicBreakDetector {
+    fn priority(&self) -> i32 {
+        priority::THEMATIC_BREAK
+    }
+
+    fn detect(&self, ctx: &mut DetectContext) -> Result<Option<Detection>, FormatterError> {
+        let is_break = ctx.parser.is_thematic_break(ctx.line.indent, &ctx.text);
+        Ok(is_break.then_some(Detection::Block(Block::ThematicBreak)))
+    }
+}
+
+/// Bullet and numbered lists; the list consumes its items and their bodies.
+struct ListDetector;
+
+impl BlockDetector for ListDetector {
+    fn priority(&self) -> i32 {
+        priority::LIST
+    }
+
+    fn detect(&self, ctx: &mut DetectContext) -> Result<Option<Detection>, FormatterError> {
+        if !ctx.parser.config.detect_lists || ctx.lines.at_max_depth() {
+            return Ok(None);
+        }
+        let Some(mut marker) = ctx.parser.match_item(&ctx.text, None) else {
+            return Ok(None);
+        };
+        // The text may have had its spaces collapsed; the content column comes
+        // from the line as written
+        if let Some(written) = ctx.parser.match_item(&ctx.line.text, None) {
+            marker.offset = written.offset;
+        }
+        // As in CommonMark, only a list starting at 1 may interrupt a paragraph,
+        // so wrapped prose like "until\n2019. The" or "with\nI. Newton" stays prose
+        let interrupts = matches!(
+            marker.numbering,
+            None | Some((ListStyle::Decimal | ListStyle::LowerAlpha | ListStyle::LowerRoman, 1))
+        );
+        if ctx.in_paragraph && !interrupts {
+            return Ok(None);
+        }
+        let block = ctx.parser.parse_list(ctx.lines, ctx.line, marker)?;
+        Ok(Some(Detection::Block(block)))
+    }
+}
+
+/// Setext headings: the underline on the next line turns this line into a heading.
+struct SetextHeadingDetector;
+
+impl BlockDetector for SetextHeadingDetector {
+    fn priority(&self) -> i32 {
+        priority::SETEXT_HEADING
+    }
+
+    fn detect(&self, ctx: &mut DetectContext) -> Result<Option<Detection>, FormatterError> {
+        // Within a paragraph, `parse_step` promotes the whole paragraph instead
+        if ctx.text.is_empty() || ctx.in_paragraph {
+            return Ok(None);
+        }
+        let Some(level) = ctx.parser.setext_level(ctx.lines)? else {
+            return Ok(None);
+        };
+        ctx.lines.next_line()?;
+        Ok(Some(Detection::Block(Block::Heading(level, parse_inlines(&ctx.text)))))
+    }
+}
+
+/// The opt-in heading heuristics for unmarked plain text.
+struct HeadingHeuristicsDetector;
+
+impl BlockDetector for HeadingHeuristicsDetector {
+    fn priority(&self) -> i32 {
+        priority::HEADING_HEURISTICS
+    }
+
+    fn detect(&self, ctx: &mut DetectContext) -> Result<Option<Detection>, FormatterError> {
+        if ctx.text.is_empty() {
+            return Ok(None);
+        }
+        let level = ctx.parser.guess_heading_level(&ctx.text, ctx.in_paragraph, ctx.lines)?;
+        Ok(level.map(|level| Detection::Block(Block::Heading(level, parse_inlines(&ctx.text)))))
+    }
+}
+
+/// Whether a line is a grid table border such as `+-----+-----+`.
+fn is_grid_border(text: &str) -> bool {
+    let text = text.trim();
+    text.len() > 1
+        && text.starts_with('+')
+        && text.contains(['-', '='])
+        && text.chars().all(|c| matches!(c, '+' | '-' | '=' | ':'))
+}
+
+/// Splits `| a | b |` into trimmed cells; `\|` is an escaped pipe, not a separator.
+fn split_pipe_row(row: &str) -> Vec<String> {
+    let row = row.strip_prefix('|').unwrap_or(row);
+    let row = match row.strip_suffix('|') {
+        Some(inner) if !inner.ends_with('\\') => inner,
+        _ => row,
+    };
+
+    let mut cells = vec![];
+    let mut cell = String::new();
+    let mut chars = row.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // An escaped pipe is part of the cell, even inside a code span or image alt text
+            '\\' if chars.peek() == Some(&'|') => {
+                cell.push('|');
+                chars.next();
+            }
+            '|' => cells.push(std::mem::take(&mut cell).trim().to_string()),
+            _ => cell.push(c),
+        }
+    }
+    cells.push(cell.trim().to_string());
+    cells
+}
+
+/// Reads the alignment out of a delimiter cell such as `:---:`.
+fn cell_alignment(cell: &str) -> Alignment {
+    match (cell.starts_with(':'), cell.ends_with(':')) {
+        (true, true) => Alignment::Center,
+        (true, false) => Alignment::Left,
+        (false, true) => Alignment::Right,
+        (false, false) => Alignment::None,
+    }
+}
+
+/// Whether a cell holds a number such as `42`, `-3.5`, `1,200` or `$9.99`.
+fn is_numeric(cell: &str) -> bool {
+    let value = cell.trim_start_matches(['$', '€', '£']).trim_end_matches('%');
+    value.replace(',', "").parse::<f64>().is_ok()
+}
+
+/// The built-in word list, embedded at compile time.
+const BUILTIN_WORDS: &str = include_str!("words.txt");
+
+/// First halves that usually form real hyphenated compounds ("self-evident").
+const COMPOUND_PREFIXES: [&str; 16] = [
+    "all", "anti", "co", "cross", "ex", "half", "mid", "multi", "non", "post", "pre", "quasi", "re", "self", "semi",
+    "well",
+];
+
+/// Collects the built-in and user-supplied word lists enabled in `config`.
+fn load_words(config: &Config) -> (HashSet<String>, Option<FormatterError>) {
+    let mut sources = vec![];
+    let mut error = None;
+    if config.builtin_word_list {
+        sources.push(BUILTIN_WORDS.to_string());
+    }
+    if let Some(path) = &config.word_list {
+        match std::fs::read_to_string(path) {
+            Ok(words) => sources.push(words),
+            Err(e) => {
+                let e = io::Error::new(e.kind(), format!("cannot read word list '{}': {}", path, e));
+                error = Some(FormatterError::IoError(e));
+            }
+        }
+    }
+
+    let words = sources
+        .iter()
+        .flat_map(|source| source.lines())
+        .map(str::trim)
+        .filter(|word| !word.is_empty() && !word.starts_with('#'))
+        .map(str::to_lowercase)
+        .collect();
+    (words, error)
+}
+
+/// The run of letters at the end of `text` ("" if it ends in anything else).
+fn trailing_word(text: &str) -> &str {
+    let start = text
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_alphabetic())
+        .last()
+        .map_or(text.len(), |(i, _)| i);
+    &text[start..]
+}
+
+/// The run of letters at the start of `text`.
+fn leading_word(text: &str) -> &str {
+    let end = text
+        .char_indices()
+        .find(|(_, c)| !c.is_alphabetic())
+        .map_or(text.len(), |(i, _)| i);
+    &text[..end]
+}
+
+/// How deep container blocks may nest; deeper markers are read as text, which
+/// keeps the recursion of nested parsing off the end of the stack.
+const MAX_NESTING: usize = 100;
+
+/// Longest line, in words and characters, that the heading heuristics consider.
+const MAX_HEADING_WORDS: usize = 10;
+const MAX_HEADING_CHARS: usize = 80;
+
+/// Minor words that stay lowercase in Title Case ("Notes on the Release").
+const TITLE_CASE_MINOR_WORDS: [&str; 17] = [
+    "a", "an", "and", "as", "at", "but", "by", "for", "from", "in", "nor", "of", "on", "or",
+    "the", "to", "with",
+];
+
+/// Whether a line is ALL CAPS: at least three letters, none of them lowercase.
+fn is_all_caps(text: &str) -> bool {
+    let letters: Vec<char> = text.chars().filter(|c| c.is_alphabetic()).collect();
+    letters.len() >= 3 && letters.iter().all(|c| !c.is_lowercase())
+}
+
+/// Whether a line is Title Case: two or more words, each capitalized except
+/// minor words after the first. Words starting with a digit or symbol are ignored.
+fn is_title_case(words: &[&str]) -> bool {
+    if words.len() < 2 {
+        return false;
+    }
+    words.iter().enumerate().all(|(i, word)| {
+        let Some(first) = word.chars().next() else {
+            return true;
+        };
+        if !first.is_alphabetic() {
+            return true;
+        }
+        first.is_uppercase() || (i > 0 && TITLE_CASE_MINOR_WORDS.contains(&word.to_lowercase().as_str()))
+    })
+}
+
+/// Strips one `>` marker and the optional space after it.
+fn strip_quote_marker(text: &str) -> String {
+    let rest = text.strip_prefix('>').unwrap_or(text);
+    rest.strip_prefix([' ', '\t']).unwrap_or(rest).to_string()
+}
+
+/// Removes up to `columns` columns of leading whitespace (tabs count as four).
+fn strip_indent(raw: &str, columns: usize) -> &str {
+    let mut col = 0;
+    for (i, c) in raw.char_indices() {
+        if col >= columns || !matches!(c, ' ' | '\t') {
+            return &raw[i..];
+        }
+        col = if c == '\t' { col + 4 - col % 4 } else { col + 1 };
+        if col > columns {
+            // A tab reaching past the limit is kept rather than split
+            return &raw[i..];
+        }
+    }
+    ""
+}
+
+/// Parses inline markup out of a block's text: `*emphasis*`, `**strong**`,
+/// `` `code` ``, `[links](url)`, `![images](url)` and backslash hard breaks.
+///
+/// Delimiters that don't pair up are kept as literal text.
+pub(crate) fn parse_inlines(text: &str) -> Vec<Inline> {
+    let chars: Vec<char> = text.chars().collect();
+    parse_inline_range(&chars)
+}
+
+fn parse_inline_range(chars: &[char]) -> Vec<Inline> {
+    let index = InlineIndex::new(chars);
+    let mut out = vec![];
+    let mut text = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let parsed = match chars[i] {
+            '\\' => match chars.get(i + 1) {
+                Some('\n') => Some((Inline::LineBreak, i + 2)),
+                Some(&c) if c.is_ascii_punctuation() => {
+                    text.push(c);
+                    i += 2;
+                    continue;
+                }
+                _ => None,
+            },
+            '`' => {
+                let run = run_length(chars, i);
+                match index.code_span_end(i + run, run) {
+                    Some(close) => {
+                        let code: String = chars[i + run..close]
+                            .iter()
+                            .map(|&c| if c == '\n' { ' ' } else { c })
+                            .collect();
+                        // One space of padding on both sides is not part of the code
+                        let code = match code.strip_prefix(' ').and_then(|c| c.strip_suffix(' ')) {
+                            Some(inner) if !inner.trim().is_empty() => inner.to_string(),
+                            _ => code,
+                        };
+                        Some((Inline::Code(code), close + run))
+                    }
+                    None => {
+                        // No closing run: the backticks are literal
+                        text.extend(&chars[i..i + run]);
+                        i += run;
+                        continue;
+                    }
+                }
+            }
+            '!' if chars.get(i + 1) == Some(&'[') => parse_link(chars, &index, i + 1).map(|(label, url, end)| {
+                let alt = chars[i + 2..label].iter().collect();
+                (Inline::Image(alt, url), end)
+            }),
+            '[' => parse_link(chars, &index, i)
+                .filter(|&(label, ..)| !index.holds_link(i, label))
+                .map(|(label, url, end)| (Inline::Link(parse_inline_range(&chars[i + 1..label]), url), end)),
+            '*' | '_' => {
+                let parsed = parse_emphasis(chars, &index, i);
+                if parsed.is_none() {
+                    // Keep the whole delimiter run literal so it can't half-match later
+                    let run = run_length(chars, i);
+                    text.extend(&chars[i..i + run]);
+                    i += run;
+                    continue;
+                }
+                parsed
+            }
+            _ => None,
+        };
+
+        match parsed {
+            Some((inline, end)) => {
+                if !text.is_empty() {
+                    out.push(Inline::Text(std::mem::take(&mut text)));
+                }
+                out.push(inline);
+                i = end;
+            }
+            None => {
+                text.push(chars[i]);
+                i += 1;
+            }
+        }
+    }
+
+    if !text.is_empty() {
+        out.push(Inline::Text(text));
+    }
+    out
+}
+
+/// Where the first run of exactly `run` `delim`s that can close emphasis starts
+/// in `text` (in chars), as `parse_inlines` finds it for an opener just before.
+pub(crate) fn emphasis_closer(text: &str, delim: char, run: usize) -> Option<usize> {
+    let chars: Vec<char> = text.chars().collect();
+    InlineIndex::new(&chars).closer(delim, run, 0)
+}
+
+/// Length of the run of identical characters starting at `start`.
+fn run_length(chars: &[char], start: usize) -> usize {
+    chars[start..].iter().take_while(|&&c| c == chars[start]).count()
+}
+
+/// Where the delimiters of an inline range are, collected in one pass so that
+/// an opener without a partner doesn't rescan the rest of the paragraph.
+struct InlineIndex {
+    /// Start of every backtick run, by run length
+    backticks: HashMap<usize, Vec<usize>>,
+    /// `*`/`_` runs that can close emphasis, by delimiter and run length
+    closers: HashMap<(char, usize), Vec<usize>>,
+    /// The `]` matching each `[`, by the position of the `[`
+    brackets: HashMap<usize, usize>,
+    /// For each position, the first `)` at or after it that isn't balanced by
+    /// a `(` in between, and the next whitespace and non-whitespace character
+    /// (`chars.len()` if there is none)
+    next_paren: Vec<usize>,
+    next_space: Vec<usize>,
+    next_text: Vec<usize>,
+    /// Parenthesis depth before each position, not counting escaped ones
+    depth: Vec<isize>,
+    /// Every `[` that opens a link (not an image) when taken on its own
+    links: Vec<usize>,
+}
+
+impl InlineIndex {
+    fn new(chars: &[char]) -> Self {
+        let len = chars.len();
+        let mut backticks: HashMap<usize, Vec<usize>> = HashMap::new();
+        let mut i = 0;
+        while i < len {
+            if chars[i] == '`' {
+                let run = run_length(chars, i);
+                backticks.entry(run).or_default().push(i);
+                i += run;
+            } else {
+                i += 1;
+            }
+        }
+
+        let mut index = InlineIndex {
+            backticks,
+            closers: HashMap::new(),
+            brackets: HashMap::new(),
+            next_paren: vec![len; len + 1],
+            next_space: vec![len; len + 1],
+            next_text: vec![len; len + 1],
+            depth: vec![0; len + 1],
+            links: vec![],
+        };
+
+        // The `)` closing at a position is the first one after it at the same depth
+        let depth = &mut index.depth;
+        let mut escaped = vec![false; len];
+        for i in 0..len {
+            escaped[i] = i > 0 && chars[i - 1] == '\\' && !escaped[i - 1];
+            depth[i + 1] = depth[i]
+                + match chars[i] {
+                    '(' if !escaped[i] => 1,
+                    ')' if !escaped[i] => -1,
+                    _ => 0,
+                };
+        }
+        let mut closing: HashMap<isize, usize> = HashMap::new();
+        for i in (0..len).rev() {
+            if chars[i] == ')' && !escaped[i] {
+                closing.insert(depth[i], i);
+            }
+            index.next_paren[i] = closing.get(&depth[i]).copied().unwrap_or(len);
+            index.next_space[i] = if chars[i].is_whitespace() { i } else { index.next_space[i + 1] };
+            index.next_text[i] = if chars[i].is_whitespace() { index.next_text[i + 1] } else { i };
+        }
+
+        // Same escapes as the bracket scan in `parse_link`
+        let mut open = vec![];
+        let mut j = 0;
+        while j < len {
+            match chars[j] {
+                '\\' => j += 1,
+                '[' => open.push(j),
+                ']' => {
+                    if let Some(start) = open.pop() {
+                        index.brackets.insert(start, j);
+                    }
+                }
+                _ => {}
+            }
+            j += 1;
+        }
+
+        // Each `]` ends at most one label, so this looks at every url once
+        index.links = (0..len)
+            .filter(|&j| chars[j] == '[' && !(j > 0 && chars[j - 1] == '!' && !escaped[j - 1]))
+            .filter(|&j| link_destination(chars, &index, j).is_some())
+            .collect();
+
+        // Same tokens as `parse_inline_range`: escapes, code spans and links are
+        // skipped, delimiter runs are taken whole. Every opener is then at a
+        // token boundary, so its closer is the first one recorded after it
+        let mut j = 0;
+        while j < len {
+            match chars[j] {
+                '\\' => j += 2,
+                '`' => {
+                    let run = run_length(chars, j);
+                    j = index.code_span_end(j + run, run).map_or(j + run, |end| end + run);
+                }
+                '[' => j = parse_link(chars, &index, j)
+                    .filter(|&(close, ..)| !index.holds_link(j, close))
+                    .map_or(j + 1, |(_, _, end)| end),
+                '!' if chars.get(j + 1) == Some(&'[') => {
+                    j = parse_link(chars, &index, j + 1).map_or(j + 1, |(_, _, end)| end);
+                }
+                delim @ ('*' | '_') => {
+                    let run = run_length(chars, j);
+                    let closes = j > 0
+                        && !chars[j - 1].is_whitespace()
+                        && (delim == '*' || !chars.get(j + run).is_some_and(|c| c.is_alphanumeric()));
+                    if closes && run <= 3 {
+                        index.closers.entry((delim, run)).or_default().push(j);
+                    }
+                    j += run;
+                }
+                _ => j += 1,
+            }
+        }
+        index
+    }
+
+    /// Finds the start of a backtick run of exactly `run` characters at or after `from`.
+    fn code_span_end(&self, from: usize, run: usize) -> Option<usize> {
+        first_at_or_after(self.backticks.get(&run)?, from)
+    }
+
+    /// Finds the first run of `run` `delim`s at or after `from` that can close emphasis.
+    fn closer(&self, delim: char, run: usize, from: usize) -> Option<usize> {
+        first_at_or_after(self.closers.get(&(delim, run))?, from)
+    }
+
+    /// Whether a link opens between the brackets at `open` and `close`. Links
+    /// can't nest, so only the innermost one is a link, as in CommonMark.
+    fn holds_link(&self, open: usize, close: usize) -> bool {
+        first_at_or_after(&self.links, open + 1).is_some_and(|j| j < close)
+    }
+}
+
+fn first_at_or_after(positions: &[usize], from: usize) -> Option<usize> {
+    positions.get(positions.partition_point(|&p| p < from)).copied()
+}
+
+/// Parses `[label](url)` starting at the `[`, returning the index of the closing
+/// `]`, the url, and the index just past the `)`.
+fn parse_link(chars: &[char], index: &InlineIndex, open: usize) -> Option<(usize, String, usize)> {
+    let (close, start, end, past) = link_destination(chars, index, open)?;
+    Some((close, chars[start..end].iter().collect(), past))
+}
+
+/// Finds the parts of `[label](url)` starting at the `[`: the index of the
+/// closing `]`, the start and end of the url, and the index just past the `)`.
+///
+/// The url is a single word, ignoring surrounding spaces, and may hold
+/// balanced parentheses, as in `wiki/Rust_(lang)`.
+fn link_destination(chars: &[char], index: &InlineIndex, open: usize) -> Option<(usize, usize, usize, usize)> {
+    let close = *index.brackets.get(&open)?;
+    if chars.get(close + 1) != Some(&'(') {
+        return None;
+    }
+    let start = index.next_text[close + 2];
+    let end = index.next_space[start].min(index.next_paren[start]);
+    // Only spaces may follow the url before the `)`, and a `(` in the url must
+    // be closed within it: `[a](b(c )` is not a link
+    let url_end = index.next_text[end];
+    if start >= end
+        || chars.get(url_end) != Some(&')')
+        || index.next_paren[end] != url_end
+        || index.depth[end] != index.depth[start]
+    {
+        return None;
+    }
+    Some((close, start, end, url_end + 1))
+}
+
+/// Parses emphasis (`*a*`, `_a_`), strong (`**a**`, `__a__`) or both (`***a***`)
+/// starting at a delimiter run, returning the node and the index past the closer.
+///
+/// Delimiters inside code spans and links don't close it.
+fn parse_emphasis(chars: &[char], index: &InlineIndex, start: usize) -> Option<(Inline, usize)> {
+    let delim = chars[start];
+    let run = run_length(chars, start);
+    if run > 3 {
+        return None;
+    }
+
+    // The opener must be followed by text; `_` must also not sit inside a word
+    let after = *chars.get(start + run)?;
+    if after.is_whitespace() {
+        return None;
+    }
+    if delim == '_' && start > 0 && chars[start - 1].is_alphanumeric() {
+        return None;
+    }
+
+    let close = index.closer(delim, run, start + run)?;
+    let inner = parse_inline_range(&chars[start + run..close]);
+    let node = match run {
+        1 => Inline::Emphasis(inner),
+        2 => Inline::Strong(inner),
+        _ => Inline::Strong(vec![Inline::Emphasis(inner)]),
+    };
+    Some((node, close + run))
+}
+
+/// Interprets an ordered list marker (without its delimiter) as a style and value.
+pub(crate) fn parse_enumerator(marker: &str, current: Option<ListStyle>) -> Option<(ListStyle, u32)> {
+    if let Ok(value) = marker.parse::<u32>() {
+        return Some((ListStyle::Decimal, value));
+    }
+
+    let lower = marker.chars().all(|c| c.is_ascii_lowercase());
+    let upper = marker.chars().all(|c| c.is_ascii_uppercase());
+    if !lower && !upper {
+        return None;
+    }
+    let (alpha, roman) = if lower {
+        (ListStyle::LowerAlpha, ListStyle::LowerRoman)
+    } else {
+        (ListStyle::UpperAlpha, ListStyle::UpperRoman)
+    };
+
+    let roman_value = roman_to_u32(marker);
+    if marker.len() == 1 {
+        let letter_value = (marker.as_bytes()[0].to_ascii_lowercase() - b'a' + 1) as u32;
+        return match (current, roman_value) {
+            (Some(style), _) if style == alpha => Some((alpha, letter_value)),
+            (Some(style), Some(value)) if style == roman => Some((roman, value)),
+            (_, Some(1)) => Some((roman, 1)),
+            _ => Some((alpha, letter_value)),
+        };
+    }
+
+    roman_value.map(|value| (roman, value))
+}
+
+/// Parses a (canonical) roman numeral such as "iv" or "XII".
+fn roman_to_u32(numeral: &str) -> Option<u32> {
+    let digit = |c: char| match c.to_ascii_lowercase() {
+        'i' => Some(1),
+        'v' => Some(5),
+        'x' => Some(10),
+        'l' => Some(50),
+        'c' => Some(100),
+        'd' => Some(500),
+        'm' => Some(1000),
+        _ => None,
+    };
+
+    let digits: Vec<u32> = numeral.chars().map(digit).collect::<Option<_>>()?;
+    let mut value = 0;
+    for (i, &d) in digits.iter().enumerate() {
+        match digits.get(i + 1) {
+            Some(&next) if next > d => value -= d as i64,
+            _ => value += d as i64,
+        }
+    }
+
+    // Reject non-canonical forms like "iiii" or "vx" by round-tripping
+    let value = u32::try_from(value).ok().filter(|&v| v > 0)?;
+    (u32_to_roman(value) == numeral.to_ascii_lowercase()).then_some(value)
+}
+
+/// Formats a value as a lowercase roman numeral.
+fn u32_to_roman(mut value: u32) -> String {
+    const NUMERALS: [(u32, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+
+    let mut out = String::new();
+    for (n, s) in NUMERALS {
+        while value >= n {
+            out.push_str(s);
+            value -= n;
+        }
+    }
+    out
+}
+
+/// Compiles `Config::custom_patterns` into rules sorted by descending priority.
+///
+/// Invalid patterns are left out; the first one's error is returned too.
+fn compile_rules(patterns: &[CustomPattern]) -> (Vec<CompiledRule>, Option<FormatterError>) {
+    let mut rules = Vec::with_capacity(patterns.len());
+    let mut error = None;
+    for pattern in patterns {
+        match compile_rule(pattern) {
+            Ok(rule) => rules.push(rule),
+            Err(e) => {
+                error.get_or_insert(e);
+            }
+        }
+    }
+
+    // Stable sort, so rules with equal priority keep their declaration order
+    rules.sort_by_key(|(priority, _)| std::cmp::Reverse(*priority));
+    (rules.into_iter().map(|(_, rule)| rule).collect(), error)
+}
+
+/// Compiles one custom pattern, returning it with its priority.
+fn compile_rule(pattern: &CustomPattern) -> Result<(i32, CompiledRule), FormatterError> {
+    let (name, source, action, template, level, priority) = match pattern {
+        CustomPattern::Pattern(source) => {
+            ("custom", source, RuleAction::Callout, None, None, 0)
+        }
+        CustomPattern::Rule(rule) => (
+            rule.name.as_str(),
+            &rule.pattern,
+            rule.action,
+            rule.template.as_deref(),
+            rule.level,
+            rule.priority,
+        ),
+    };
+
+    let regex = Regex::new(source).map_err(|e| {
+        FormatterError::ParseError(format!("invalid custom pattern '{}': {}", name, e))
+    })?;
+
+    if action == RuleAction::Replace && template.is_none() {
+        return Err(FormatterError::ParseError(format!(
+            "custom pattern '{}' uses the replace action but has no template",
+            name
+        )));
+    }
+
+    Ok((
+        priority,
+        CompiledRule {
+            name: name.to_string(),
+            regex,
+            action,
+            template: template.unwrap_or_default().to_string(),
+            level: level.unwrap_or(1).clamp(1, 6),
+        },
+    ))
+}
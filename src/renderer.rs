@@ -0,0 +1,93 @@
+/*!
+Output formats: the `Renderer` trait and the built-in Markdown and HTML renderers.
+*/
+
+use std::cell::Cell;
+use std::io::{self, Write};
+
+use crate::formatter::{blocks_to_html, write_markdown_block};
+use crate::schema::SCHEMA_VERSION;
+use crate::structures::{Block, Spanned};
+
+/// Turns parsed blocks into an output format.
+///
+/// Blocks are handed over one at a time as soon as they are parsed, between a
+/// single `document_start` and `document_end` call. Implement this trait to add
+/// an output format without changing `tform`, then pass it to
+/// `Formatter::format_with`.
+pub trait Renderer {
+    /// Called once before the first block (e.g. to write a header).
+    fn document_start(&self, _output: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called for each top-level block, in document order. The block derefs
+    /// to a `Block`; its `span` locates it in the input.
+    fn block(&self, block: &Spanned<Block>, output: &mut dyn Write) -> io::Result<()>;
+
+    /// Called once after the last block (e.g. to write a footer).
+    fn document_end(&self, _output: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Renders Markdown (the output of `Formatter::format_to_markdown`).
+#[derive(Debug, Default)]
+pub struct MarkdownRenderer {
+    // The marker character of the previous block if it was a list, so an
+    // adjacent list can be kept apart
+    previous_list: Cell<Option<char>>,
+}
+
+impl Renderer for MarkdownRenderer {
+    fn document_start(&self, _output: &mut dyn Write) -> io::Result<()> {
+        self.previous_list.set(None);
+        Ok(())
+    }
+
+    fn block(&self, block: &Spanned<Block>, mut output: &mut dyn Write) -> io::Result<()> {
+        let marker = write_markdown_block(block, self.previous_list.get(), &mut output)?;
+        self.previous_list.set(marker);
+        Ok(())
+    }
+}
+
+/// Renders an HTML fragment (the output of `Formatter::format_to_html`).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HtmlRenderer {
+    /// Add `data-source-line` attributes with each block's input line.
+    pub source_lines: bool,
+}
+
+impl Renderer for HtmlRenderer {
+    fn block(&self, block: &Spanned<Block>, mut output: &mut dyn Write) -> io::Result<()> {
+        blocks_to_html(std::slice::from_ref(block), self.source_lines, &mut output)
+    }
+}
+
+/// Renders the document as JSON (see `schema` for the layout), streaming one
+/// block at a time. The output is the same as serializing the `Document`.
+#[derive(Debug, Default)]
+pub struct JsonRenderer {
+    // Whether a block has been written, so the next one needs a separator
+    wrote_block: Cell<bool>,
+}
+
+impl Renderer for JsonRenderer {
+    fn document_start(&self, output: &mut dyn Write) -> io::Result<()> {
+        self.wrote_block.set(false);
+        write!(output, "{{\"version\":{},\"blocks\":[", SCHEMA_VERSION)
+    }
+
+    fn block(&self, block: &Spanned<Block>, mut output: &mut dyn Write) -> io::Result<()> {
+        if self.wrote_block.replace(true) {
+            output.write_all(b",")?;
+        }
+        serde_json::to_writer(&mut output, block)?;
+        Ok(())
+    }
+
+    fn document_end(&self, output: &mut dyn Write) -> io::Result<()> {
+        output.write_all(b"]}")
+    }
+}
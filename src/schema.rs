@@ -0,0 +1,170 @@
+/*!
+The JSON schema of the document model.
+
+`Block` and `Inline` use tuple variants, which serde can't represent as
+internally tagged objects, so they are (de)serialized through the mirror types
+below: every node is an object with a `"type"` tag and named fields. The
+document itself carries `SCHEMA_VERSION`, which changes whenever this layout
+does; documents with another version are rejected.
+*/
+
+use serde::{Deserialize, Serialize};
+
+use crate::structures::{Alignment, Block, Document, Inline, ListItem, ListStyle, Spanned, Table};
+
+/// Version of the JSON layout written by `Formatter::format_to_json`.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct DocumentRepr {
+    version: u32,
+    blocks: Vec<Spanned<Block>>,
+}
+
+impl From<Document> for DocumentRepr {
+    fn from(doc: Document) -> Self {
+        DocumentRepr {
+            version: SCHEMA_VERSION,
+            blocks: doc.blocks,
+        }
+    }
+}
+
+impl TryFrom<DocumentRepr> for Document {
+    type Error = String;
+
+    fn try_from(repr: DocumentRepr) -> Result<Self, Self::Error> {
+        if repr.version != SCHEMA_VERSION {
+            return Err(format!(
+                "unsupported document version {} (expected {})",
+                repr.version, SCHEMA_VERSION
+            ));
+        }
+        Ok(Document { blocks: repr.blocks })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum BlockRepr {
+    Heading {
+        level: u8,
+        content: Vec<Inline>,
+    },
+    Paragraph {
+        content: Vec<Inline>,
+    },
+    List {
+        items: Vec<ListItem>,
+    },
+    OrderedList {
+        style: ListStyle,
+        start: u32,
+        items: Vec<ListItem>,
+    },
+    CodeBlock {
+        info: Option<String>,
+        code: String,
+    },
+    Callout {
+        name: String,
+        content: Vec<Inline>,
+    },
+    Quote {
+        blocks: Vec<Spanned<Block>>,
+    },
+    Table {
+        alignments: Vec<Alignment>,
+        header: Vec<Vec<Inline>>,
+        rows: Vec<Vec<Vec<Inline>>>,
+    },
+    ThematicBreak,
+}
+
+impl From<Block> for BlockRepr {
+    fn from(block: Block) -> Self {
+        match block {
+            Block::Heading(level, content) => BlockRepr::Heading { level, content },
+            Block::Paragraph(content) => BlockRepr::Paragraph { content },
+            Block::List(items) => BlockRepr::List { items },
+            Block::OrderedList(style, start, items) => BlockRepr::OrderedList { style, start, items },
+            Block::CodeBlock(info, code) => BlockRepr::CodeBlock { info, code },
+            Block::Callout(name, content) => BlockRepr::Callout { name, content },
+            Block::Quote(blocks) => BlockRepr::Quote { blocks },
+            Block::Table(Table {
+                alignments,
+                header,
+                rows,
+            }) => BlockRepr::Table {
+                alignments,
+                header,
+                rows,
+            },
+            Block::ThematicBreak => BlockRepr::ThematicBreak,
+        }
+    }
+}
+
+impl From<BlockRepr> for Block {
+    fn from(repr: BlockRepr) -> Self {
+        match repr {
+            BlockRepr::Heading { level, content } => Block::Heading(level, content),
+            BlockRepr::Paragraph { content } => Block::Paragraph(content),
+            BlockRepr::List { items } => Block::List(items),
+            BlockRepr::OrderedList { style, start, items } => Block::OrderedList(style, start, items),
+            BlockRepr::CodeBlock { info, code } => Block::CodeBlock(info, code),
+            BlockRepr::Callout { name, content } => Block::Callout(name, content),
+            BlockRepr::Quote { blocks } => Block::Quote(blocks),
+            BlockRepr::Table {
+                alignments,
+                header,
+                rows,
+            } => Block::Table(Table {
+                alignments,
+                header,
+                rows,
+            }),
+            BlockRepr::ThematicBreak => Block::ThematicBreak,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum InlineRepr {
+    Text { text: String },
+    Emphasis { content: Vec<Inline> },
+    Strong { content: Vec<Inline> },
+    Code { code: String },
+    Link { content: Vec<Inline>, url: String },
+    Image { alt: String, url: String },
+    LineBreak,
+}
+
+impl From<Inline> for InlineRepr {
+    fn from(inline: Inline) -> Self {
+        match inline {
+            Inline::Text(text) => InlineRepr::Text { text },
+            Inline::Emphasis(content) => InlineRepr::Emphasis { content },
+            Inline::Strong(content) => InlineRepr::Strong { content },
+            Inline::Code(code) => InlineRepr::Code { code },
+            Inline::Link(content, url) => InlineRepr::Link { content, url },
+            Inline::Image(alt, url) => InlineRepr::Image { alt, url },
+            Inline::LineBreak => InlineRepr::LineBreak,
+        }
+    }
+}
+
+impl From<InlineRepr> for Inline {
+    fn from(repr: InlineRepr) -> Self {
+        match repr {
+            InlineRepr::Text { text } => Inline::Text(text),
+            InlineRepr::Emphasis { content } => Inline::Emphasis(content),
+            InlineRepr::Strong { content } => Inline::Strong(content),
+            InlineRepr::Code { code } => Inline::Code(code),
+            InlineRepr::Link { content, url } => Inline::Link(content, url),
+            InlineRepr::Image { alt, url } => Inline::Image(alt, url),
+            InlineRepr::LineBreak => Inline::LineBreak,
+        }
+    }
+}
@@ -1,21 +1,129 @@
 /*!
 Contains data structures representing the intermediate document model.
+
+All of them (de)serialize with serde; see `schema` for the JSON layout.
 */
 
+use serde::{Deserialize, Serialize};
+
 /// Represents a higher-level block in the parsed document.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(into = "crate::schema::BlockRepr", from = "crate::schema::BlockRepr")]
 pub enum Block {
-    Heading(u8, String), // level, text
-    Paragraph(String),
-    List(Vec<String>),   // simplest representation of a list
-    CodeBlock(String),
-    // Add more as needed (quotes, tables, etc.)
+    Heading(u8, Vec<Inline>), // level, text
+    Paragraph(Vec<Inline>),
+    List(Vec<ListItem>),
+    OrderedList(ListStyle, u32, Vec<ListItem>), // numbering style, start number, items
+    CodeBlock(Option<String>, String), // info string (e.g. "rust"), code
+    Callout(String, Vec<Inline>), // rule name, text
+    Quote(Vec<Spanned<Block>>),
+    Table(Table),
+    ThematicBreak,
+    // Add more as needed
+}
+
+/// Inline content of headings, paragraphs and list items.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(into = "crate::schema::InlineRepr", from = "crate::schema::InlineRepr")]
+pub enum Inline {
+    Text(String),
+    Emphasis(Vec<Inline>),
+    Strong(Vec<Inline>),
+    Code(String),
+    Link(Vec<Inline>, String), // text, url
+    Image(String, String),     // alt text, url
+    LineBreak,
+}
+
+/// An item of a `Block::List` or `Block::OrderedList`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListItem {
+    /// The item's own text (its first paragraph).
+    #[serde(rename = "content")]
+    pub text: Vec<Inline>,
+    /// Nested content: child lists and further paragraphs.
+    pub children: Vec<Spanned<Block>>,
+}
+
+/// A table with a header row. Every row has one cell per column.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Table {
+    pub alignments: Vec<Alignment>,
+    pub header: Vec<Vec<Inline>>,
+    pub rows: Vec<Vec<Vec<Inline>>>,
+}
+
+/// Horizontal alignment of a table column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Alignment {
+    None,
+    Left,
+    Center,
+    Right,
+}
+
+/// Numbering style of an ordered list, as written in the source text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ListStyle {
+    Decimal,    // 1. 2. 3.
+    LowerAlpha, // a. b. c.
+    UpperAlpha, // A. B. C.
+    LowerRoman, // i. ii. iii.
+    UpperRoman, // I. II. III.
+}
+
+/// A position in the input text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Position {
+    /// Byte offset from the start of the input.
+    pub offset: usize,
+    /// Line number, starting at 1.
+    pub line: usize,
+    /// Column in characters, starting at 1.
+    pub column: usize,
+}
+
+/// The part of the input a node was parsed from, from `start` up to (not including) `end`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// A node together with the span of input it was parsed from.
+///
+/// Derefs to the node, so `Spanned<Block>` can mostly be used like a `Block`.
+/// Serialized as the node's own fields plus a `span` field.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Spanned<T> {
+    #[serde(flatten)]
+    pub node: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    /// Attach a span to a node.
+    pub fn new(node: T, span: Span) -> Self {
+        Spanned { node, span }
+    }
+}
+
+impl<T> std::ops::Deref for Spanned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.node
+    }
 }
 
-/// A parsed document consists of a series of `Block` items.
-#[derive(Debug, Clone)]
+/// A parsed document consists of a series of `Block` items, each with its source span.
+/// Serialized with a `version` field (`schema::SCHEMA_VERSION`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(into = "crate::schema::DocumentRepr", try_from = "crate::schema::DocumentRepr")]
 pub struct Document {
-    pub blocks: Vec<Block>,
+    pub blocks: Vec<Spanned<Block>>,
 }
 
 impl Document {
@@ -0,0 +1,513 @@
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::process::{Command, Output, Stdio};
+
+    /// Runs the `tform` binary with `args`, feeding `stdin` to it.
+    fn tform(args: &[&str], stdin: &str) -> Output {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_tform"))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("Failed to start tform");
+        // tform may exit before reading stdin (e.g. on a usage error)
+        if let Err(e) = child.stdin.take().unwrap().write_all(stdin.as_bytes()) {
+            assert_eq!(e.kind(), std::io::ErrorKind::BrokenPipe, "Failed to write stdin");
+        }
+        child.wait_with_output().expect("Failed to wait for tform")
+    }
+
+    /// A fresh, empty scratch directory for one test.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tform_cli_{}_{}", name, std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).expect("Failed to create scratch dir");
+        dir
+    }
+
+    fn stdout(output: &Output) -> String {
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+
+    /// Test that stdin is formatted to stdout, as Markdown by default.
+    #[test]
+    fn test_cli_stdin_to_stdout() {
+        let output = tform(&[], "#Title\nSome   text.\n");
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(stdout(&output), "# Title\n\nSome text.\n\n");
+
+        let output = tform(&["--to", "html"], "#Title\n");
+        assert_eq!(stdout(&output), "<h1>Title</h1>\n");
+
+        let output = tform(&["--to=json"], "hi\n");
+        assert!(stdout(&output).starts_with(r#"{"version":1,"blocks":[{"type":"paragraph""#));
+        assert!(stdout(&output).ends_with("]}\n"), "{}", stdout(&output));
+    }
+
+    /// Test that every config field can be overridden, after the config file is applied.
+    #[test]
+    fn test_cli_config_overrides() {
+        let dir = scratch_dir("config");
+        let config = dir.join("config.toml");
+        std::fs::write(&config, "remove_extra_spaces = false\ndetect_lists = false\n").unwrap();
+        let config = config.to_str().unwrap();
+
+        let output = tform(&["-c", config], "a  b\n- item\n");
+        assert_eq!(stdout(&output), "a  b - item\n\n");
+
+        let output = tform(&["-c", config, "--remove-extra-spaces", "--detect-lists=true"], "a  b\n- item\n");
+        assert_eq!(stdout(&output), "a b\n\n- item\n\n");
+
+        let output = tform(&["--no-detect-headings", "--custom-patterns", "^NOTE"], "# x\nNOTE y\n");
+        assert_eq!(stdout(&output), "\\# x\n\n> [!CUSTOM]\n> NOTE y\n\n");
+
+        let output = tform(&["--indented-code-width", "2", "--to", "html"], "  code\n");
+        assert_eq!(stdout(&output), "<pre><code>code</code></pre>\n");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    /// Test that input files are read in order as one document, and `-o` writes to a file.
+    #[test]
+    fn test_cli_files_and_output() {
+        let dir = scratch_dir("files");
+        std::fs::write(dir.join("a.txt"), "# One\n").unwrap();
+        std::fs::write(dir.join("b.txt"), "two\n").unwrap();
+        let out = dir.join("out.md");
+
+        let output = tform(
+            &[
+                dir.join("a.txt").to_str().unwrap(),
+                dir.join("b.txt").to_str().unwrap(),
+                "-o",
+                out.to_str().unwrap(),
+            ],
+            "",
+        );
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(stdout(&output), "");
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "# One\n\ntwo\n\n");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    /// Test the distinct exit codes for usage, IO and parse errors.
+    #[test]
+    fn test_cli_exit_codes() {
+        let output = tform(&["--no-such-option"], "");
+        assert_eq!(output.status.code(), Some(2), "{:?}", output);
+        let output = tform(&["--to", "pdf"], "");
+        assert_eq!(output.status.code(), Some(2), "{:?}", output);
+
+        let output = tform(&["/nonexistent/input.txt"], "");
+        assert_eq!(output.status.code(), Some(3), "{:?}", output);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("/nonexistent/input.txt"));
+
+        let output = tform(&["--custom-patterns", "("], "x\n");
+        assert_eq!(output.status.code(), Some(4), "{:?}", output);
+
+        let dir = scratch_dir("bad_config");
+        let config = dir.join("bad.toml");
+        std::fs::write(&config, "detect_lists = \"yes\"\n").unwrap();
+        let output = tform(&["--config", config.to_str().unwrap()], "");
+        assert_eq!(output.status.code(), Some(4), "{:?}", output);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    /// Test that a reader closing the pipe early ends tform quietly and successfully.
+    #[test]
+    fn test_cli_closed_pipe() {
+        use std::io::Read;
+
+        let dir = scratch_dir("closed_pipe");
+        let input = dir.join("big.txt");
+        std::fs::write(&input, "Some   text.\n\n".repeat(100_000)).unwrap();
+
+        let mut child = Command::new(env!("CARGO_BIN_EXE_tform"))
+            .arg(&input)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("Failed to start tform");
+        let mut head = [0; 16];
+        child.stdout.take().unwrap().read_exact(&mut head).unwrap();
+        let output = child.wait_with_output().expect("Failed to wait for tform");
+
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    /// Test that an input without a final newline doesn't run into the next one.
+    #[test]
+    fn test_cli_inputs_without_final_newline() {
+        let dir = scratch_dir("final_newline");
+        let a = dir.join("a.txt");
+        std::fs::write(&a, "# A").unwrap();
+        let b = dir.join("b.txt");
+        std::fs::write(&b, "# B\n").unwrap();
+
+        let output = tform(&[a.to_str().unwrap(), b.to_str().unwrap()], "");
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(stdout(&output), "# A\n\n# B\n\n");
+        let output = tform(&[a.to_str().unwrap(), "-"], "# C");
+        assert_eq!(stdout(&output), "# A\n\n# C\n\n");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    /// Test that an output file that is also an input is refused, leaving it intact.
+    #[test]
+    fn test_cli_output_is_input() {
+        let dir = scratch_dir("output_is_input");
+        let notes = dir.join("notes.txt");
+        std::fs::write(&notes, "Some   notes.\n").unwrap();
+        let other = dir.join("other.txt");
+        std::fs::write(&other, "More.\n").unwrap();
+
+        let output = tform(&[notes.to_str().unwrap(), "-o", notes.to_str().unwrap()], "");
+        assert_eq!(output.status.code(), Some(2), "{:?}", output);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("is also an input"));
+        // The same file under another name
+        let alias = dir.join(".").join("notes.txt");
+        let output = tform(&[other.to_str().unwrap(), notes.to_str().unwrap(), "-o", alias.to_str().unwrap()], "");
+        assert_eq!(output.status.code(), Some(2), "{:?}", output);
+        assert_eq!(std::fs::read_to_string(&notes).unwrap(), "Some   notes.\n");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    /// Lists the files under `dir`, relative and sorted, with '/' separators.
+    fn tree(dir: &std::path::Path) -> Vec<String> {
+        let mut files = vec![];
+        let mut dirs = vec![dir.to_path_buf()];
+        while let Some(next) = dirs.pop() {
+            for entry in std::fs::read_dir(next).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else {
+                    let rel = path.strip_prefix(dir).unwrap();
+                    files.push(rel.to_string_lossy().replace('\\', "/"));
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+
+    /// Test that a directory is mirrored into the output directory, honoring the globs.
+    #[test]
+    fn test_cli_directory_mode() {
+        let dir = scratch_dir("batch");
+        let src = dir.join("in");
+        std::fs::create_dir_all(src.join("sub/drafts")).unwrap();
+        std::fs::write(src.join("a.txt"), "#A\n").unwrap();
+        std::fs::write(src.join("sub/b.txt"), "b\n").unwrap();
+        std::fs::write(src.join("sub/drafts/c.txt"), "c\n").unwrap();
+        std::fs::write(src.join("notes.log"), "log\n").unwrap();
+        let out = dir.join("out");
+        let (src_arg, out_arg) = (src.to_str().unwrap(), out.to_str().unwrap());
+
+        let output = tform(&[src_arg, "-o", out_arg, "--exclude", "drafts", "-j", "2"], "");
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(stdout(&output), "2 converted, 0 skipped, 0 failed\n");
+        assert_eq!(tree(&out), ["a.md", "sub/b.md"]);
+        assert_eq!(std::fs::read_to_string(out.join("a.md")).unwrap(), "# A\n\n");
+
+        let html = dir.join("html");
+        let output = tform(
+            &[src_arg, "-o", html.to_str().unwrap(), "-t", "html", "--include", "**/*.log", "--include", "sub/**/*.txt"],
+            "",
+        );
+        assert_eq!(stdout(&output), "3 converted, 0 skipped, 0 failed\n");
+        assert_eq!(tree(&html), ["notes.html", "sub/b.html", "sub/drafts/c.html"]);
+
+        // Directory mode needs an output directory, and batch options need a directory
+        assert_eq!(tform(&[src_arg], "").status.code(), Some(2));
+        assert_eq!(tform(&["--include", "*.md"], "").status.code(), Some(2));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    /// Test that unchanged files are skipped, by content hash and by modification time.
+    #[test]
+    fn test_cli_directory_skip_unchanged() {
+        let dir = scratch_dir("batch_skip");
+        let src = dir.join("in");
+        std::fs::create_dir_all(&src).unwrap();
+        std::fs::write(src.join("a.txt"), "a\n").unwrap();
+        std::fs::write(src.join("b.txt"), "b\n").unwrap();
+        let out = dir.join("out");
+        let (src_arg, out_arg) = (src.to_str().unwrap(), out.to_str().unwrap());
+
+        let hash = [src_arg, "-o", out_arg, "--skip-unchanged", "hash"];
+        assert_eq!(stdout(&tform(&hash, "")), "2 converted, 0 skipped, 0 failed\n");
+        assert_eq!(stdout(&tform(&hash, "")), "0 converted, 2 skipped, 0 failed\n");
+        std::fs::write(src.join("b.txt"), "changed\n").unwrap();
+        assert_eq!(stdout(&tform(&hash, "")), "1 converted, 1 skipped, 0 failed\n");
+        assert_eq!(std::fs::read_to_string(out.join("b.md")).unwrap(), "changed\n\n");
+
+        // A different config invalidates the cache
+        let mut with_option = hash.to_vec();
+        with_option.push("--no-detect-lists");
+        assert_eq!(stdout(&tform(&with_option, "")), "2 converted, 0 skipped, 0 failed\n");
+
+        // Outputs written above are newer than their inputs
+        let mtime = [src_arg, "-o", out_arg, "--skip-unchanged", "mtime"];
+        assert_eq!(stdout(&tform(&mtime, "")), "0 converted, 2 skipped, 0 failed\n");
+        std::fs::remove_file(out.join("a.md")).unwrap();
+        assert_eq!(stdout(&tform(&mtime, "")), "1 converted, 1 skipped, 0 failed\n");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    /// Test that a failing file is reported without stopping the others.
+    #[cfg(unix)]
+    #[test]
+    fn test_cli_directory_failures() {
+        let dir = scratch_dir("batch_fail");
+        let src = dir.join("in");
+        std::fs::create_dir_all(&src).unwrap();
+        std::fs::write(src.join("a.txt"), "a\n").unwrap();
+        std::os::unix::fs::symlink("missing", src.join("broken.txt")).unwrap();
+        let out = dir.join("out");
+
+        let output = tform(&[src.to_str().unwrap(), "-o", out.to_str().unwrap()], "");
+        assert_eq!(output.status.code(), Some(3), "{:?}", output);
+        assert_eq!(stdout(&output), "1 converted, 0 skipped, 1 failed\n");
+        assert!(String::from_utf8_lossy(&output.stderr).contains("broken.txt"));
+        assert_eq!(tree(&out), ["a.md"]);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    /// Test that inputs which would be written to the same output fail instead
+    /// of overwriting each other.
+    #[test]
+    fn test_cli_directory_output_collisions() {
+        let dir = scratch_dir("batch_collide");
+        let src = dir.join("in");
+        std::fs::create_dir_all(&src).unwrap();
+        std::fs::write(src.join("a.txt"), "from txt\n").unwrap();
+        std::fs::write(src.join("a.md"), "from md\n").unwrap();
+        std::fs::write(src.join("b.txt"), "b\n").unwrap();
+        let out = dir.join("out");
+
+        let output = tform(&[src.to_str().unwrap(), "-o", out.to_str().unwrap(), "--include", "*"], "");
+        assert_eq!(output.status.code(), Some(3), "{:?}", output);
+        assert_eq!(stdout(&output), "1 converted, 0 skipped, 2 failed\n");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("a.md would also be written from") && stderr.contains("a.txt"), "{}", stderr);
+        assert_eq!(tree(&out), ["b.md"]);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    /// Polls `condition` until it holds, failing the test after a few seconds.
+    fn wait_for(what: &str, condition: impl Fn() -> bool) {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while !condition() {
+            assert!(std::time::Instant::now() < deadline, "Timed out waiting for {}", what);
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+    }
+
+    /// Test that watch mode reconverts changed and new files, and reloads the config.
+    #[test]
+    fn test_cli_watch() {
+        let dir = scratch_dir("watch");
+        let src = dir.join("in");
+        std::fs::create_dir_all(&src).unwrap();
+        std::fs::write(src.join("a.txt"), "#A\n").unwrap();
+        let config = dir.join("config.toml");
+        std::fs::write(&config, "detect_headings = true\n").unwrap();
+        let out = dir.join("out");
+        let read = |name: &str| std::fs::read_to_string(out.join(name)).unwrap_or_default();
+
+        let mut child = Command::new(env!("CARGO_BIN_EXE_tform"))
+            .args(["watch", src.to_str().unwrap(), "-o", out.to_str().unwrap()])
+            .args(["-c", config.to_str().unwrap(), "--interval", "20"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Failed to start tform watch");
+
+        wait_for("the first conversion", || read("a.md") == "# A\n\n");
+        std::fs::write(src.join("a.txt"), "#A changed\n").unwrap();
+        wait_for("a changed file", || read("a.md") == "# A changed\n\n");
+        std::fs::write(src.join("b.txt"), "#B\n").unwrap();
+        wait_for("a new file", || read("b.md") == "# B\n\n");
+
+        // An invalid config is ignored; a valid one reconverts everything
+        std::fs::write(&config, "detect_headings = \n").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        std::fs::write(&config, "detect_headings = false\n").unwrap();
+        wait_for("the config reload", || read("a.md") == "\\#A changed\n\n" && read("b.md") == "\\#B\n\n");
+
+        child.kill().ok();
+        child.wait().ok();
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    /// Test that watch mode, like the other modes, ignores `tform_config.toml` without `--config`.
+    #[test]
+    fn test_cli_watch_without_config() {
+        let dir = scratch_dir("watch_no_config");
+        let src = dir.join("in");
+        std::fs::create_dir_all(&src).unwrap();
+        std::fs::write(src.join("a.txt"), "#A\n").unwrap();
+        std::fs::write(dir.join("tform_config.toml"), "detect_headings = false\n").unwrap();
+        let out = dir.join("out");
+        let read = |name: &str| std::fs::read_to_string(out.join(name)).unwrap_or_default();
+
+        let mut child = Command::new(env!("CARGO_BIN_EXE_tform"))
+            .args(["watch", "in", "-o", "out", "--interval", "20"])
+            .current_dir(&dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Failed to start tform watch");
+
+        wait_for("the first conversion", || read("a.md") == "# A\n\n");
+
+        child.kill().ok();
+        child.wait().ok();
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    /// Test that watch mode removes the outputs of deleted files and keeps the hash cache current.
+    #[test]
+    fn test_cli_watch_deletes_and_caches() {
+        let dir = scratch_dir("watch_delete");
+        let src = dir.join("in");
+        std::fs::create_dir_all(&src).unwrap();
+        std::fs::write(src.join("a.txt"), "a\n").unwrap();
+        std::fs::write(src.join("b.txt"), "b\n").unwrap();
+        let out = dir.join("out");
+        let (src_arg, out_arg) = (src.to_str().unwrap(), out.to_str().unwrap());
+        let cache = || std::fs::read_to_string(out.join(".tform-cache")).unwrap_or_default();
+
+        let mut child = Command::new(env!("CARGO_BIN_EXE_tform"))
+            .args(["watch", src_arg, "-o", out_arg, "--skip-unchanged", "hash", "--interval", "20"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Failed to start tform watch");
+
+        wait_for("the first conversion", || cache().lines().count() == 2);
+        std::fs::remove_file(src.join("b.txt")).unwrap();
+        wait_for("the output to be removed", || !out.join("b.md").exists() && cache().lines().count() == 1);
+        let before = cache();
+        std::fs::write(src.join("a.txt"), "changed\n").unwrap();
+        wait_for("the cache update", || cache() != before && cache().lines().count() == 1);
+
+        child.kill().ok();
+        child.wait().ok();
+        let output = tform(&[src_arg, "-o", out_arg, "--skip-unchanged", "hash"], "");
+        assert_eq!(stdout(&output), "0 converted, 1 skipped, 0 failed\n");
+        assert_eq!(std::fs::read_to_string(out.join("a.md")).unwrap(), "changed\n\n");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    /// Test that watch mode checks its arguments before starting.
+    #[test]
+    fn test_cli_watch_usage() {
+        assert_eq!(tform(&["watch"], "").status.code(), Some(2));
+        assert_eq!(tform(&["--interval", "10"], "").status.code(), Some(2));
+
+        let dir = scratch_dir("watch_usage");
+        let config = dir.join("bad.toml");
+        std::fs::write(&config, "detect_lists = 1\n").unwrap();
+        let (dir_arg, config_arg) = (dir.to_str().unwrap(), config.to_str().unwrap());
+        let out = dir.join("out");
+        let output = tform(&["watch", dir_arg, "-o", out.to_str().unwrap(), "-c", config_arg], "");
+        assert_eq!(output.status.code(), Some(4), "{:?}", output);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    /// Test that `--check` reports unformatted inputs and sets the exit code, without writing.
+    #[test]
+    fn test_cli_check() {
+        let dir = scratch_dir("check");
+        let (messy, clean) = (dir.join("messy.md"), dir.join("clean.md"));
+        std::fs::write(&messy, "#Title\nSome   text.\n").unwrap();
+        std::fs::write(&clean, "# Title\n\nSome text.\n").unwrap();
+        let (messy_arg, clean_arg) = (messy.to_str().unwrap(), clean.to_str().unwrap());
+
+        let output = tform(&["--check", messy_arg, clean_arg], "");
+        assert_eq!(output.status.code(), Some(1), "{:?}", output);
+        assert_eq!(stdout(&output), format!("Would reformat: {}\n", messy_arg));
+        assert_eq!(std::fs::read_to_string(&messy).unwrap(), "#Title\nSome   text.\n");
+
+        let output = tform(&["--check", clean_arg], "");
+        assert_eq!(output.status.code(), Some(0), "{:?}", output);
+        assert_eq!(stdout(&output), "");
+
+        // The formatter's own output, with its trailing blank line, is formatted too
+        let output = tform(&["--check"], "# Title\n\nSome text.\n\n");
+        assert_eq!(output.status.code(), Some(0), "{:?}", output);
+        let output = tform(&["--check", "-"], "Some   text.\n");
+        assert_eq!(output.status.code(), Some(1), "{:?}", output);
+        assert_eq!(stdout(&output), "Would reformat: <stdin>\n");
+
+        assert_eq!(tform(&["--check", "-t", "html"], "").status.code(), Some(2));
+        assert_eq!(tform(&["--check", dir.to_str().unwrap()], "").status.code(), Some(2));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    /// Test that `--diff` prints a unified diff, exiting with 1 only together with `--check`.
+    #[test]
+    fn test_cli_diff() {
+        let mut input = String::from("#Title\n\n");
+        for i in 1..=8 {
+            input.push_str(&format!("Paragraph {}.\n\n", i));
+        }
+        input.push_str("Last   one.");
+
+        let output = tform(&["--diff"], &input);
+        assert_eq!(output.status.code(), Some(0), "{:?}", output);
+        assert_eq!(
+            stdout(&output),
+            "--- <stdin>
++++ <stdin>
+@@ -1,4 +1,4 @@
+-#Title
++# Title
+ 
+ Paragraph 1.
+ 
+@@ -16,4 +16,4 @@
+ 
+ Paragraph 8.
+ 
+-Last   one.
+\\ No newline at end of file
++Last one.
+"
+        );
+
+        let output = tform(&["--check", "--diff"], &input);
+        assert_eq!(output.status.code(), Some(1), "{:?}", output);
+        let output = tform(&["--check", "--diff"], "Fine.\n");
+        assert_eq!(output.status.code(), Some(0), "{:?}", output);
+        assert_eq!(stdout(&output), "");
+    }
+
+    /// Test that `--diff` handles a large input in which every line changes.
+    #[test]
+    fn test_cli_diff_large_rewrite() {
+        let input: String = (0..20000).map(|i| format!("Line   {}.\n\n", i)).collect();
+        let output = tform(&["--diff"], &input);
+        assert_eq!(output.status.code(), Some(0), "{:?}", output);
+        let diff = stdout(&output);
+        let lines: Vec<&str> = diff.lines().collect();
+        assert_eq!(lines[2], "@@ -1,40000 +1,39999 @@");
+        assert_eq!(lines.iter().filter(|line| line.starts_with("-Line   ")).count(), 20000);
+        assert_eq!(lines.iter().filter(|line| line.starts_with("+Line ")).count(), 20000);
+    }
+}
@@ -136,6 +136,7 @@ mod tests {
 
     /// Optional: Test custom Config variations if your parser handles them (e.g., removing extra spaces).
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_custom_config() {
         // Example: If your config can disable heading detection, we can test that:
         let mut config = Config::default();
@@ -152,4 +153,1216 @@ mod tests {
         assert!(!output.contains("# Not A Heading"), "Heading was incorrectly formatted");
         assert!(output.contains("Not A Heading"), "Expected text missing in output");
     }
+
+    /// Test that custom patterns become named rules applied before built-in detection.
+    #[test]
+    fn test_custom_rules() {
+        use tform::config::{CustomPattern, CustomRule, RuleAction};
+
+        let rule = |name: &str, pattern: &str, action, template: Option<&str>, priority| {
+            CustomPattern::Rule(CustomRule {
+                name: name.to_string(),
+                pattern: pattern.to_string(),
+                action,
+                template: template.map(str::to_string),
+                level: Some(2),
+                priority,
+            })
+        };
+
+        let config = Config {
+            custom_patterns: vec![
+                CustomPattern::Pattern("(?i)^todo".to_string()),
+                rule("draft", r"^DRAFT", RuleAction::Drop, None, 0),
+                rule("chapter", r"^Chapter:\s*(.*)$", RuleAction::Heading, None, 0),
+                // Higher priority, so it rewrites "FIXME" before the todo rule sees the line
+                rule("fixme", r"^FIXME", RuleAction::Replace, Some("TODO"), 5),
+            ],
+            ..Config::default()
+        };
+
+        let formatter = Formatter::new(config);
+        let input = "Chapter: Intro\nDRAFT do not publish\nFIXME: tidy up\n# Real heading";
+        let output = formatter
+            .format_to_html(Cursor::new(input))
+            .expect("Failed to format with custom rules");
+
+        assert!(output.contains("<h2>Intro</h2>"), "Heading rule not applied");
+        assert!(!output.contains("DRAFT"), "Drop rule not applied");
+        assert!(
+            output.contains("<div class=\"callout callout-custom\">TODO: tidy up</div>"),
+            "Replace rule should feed the callout rule: {}",
+            output
+        );
+        assert!(output.contains("<h1>Real heading</h1>"), "Built-in detection lost");
+    }
+
+    /// Test that a Replace rule may turn a line into a longer list item.
+    #[test]
+    fn test_replace_rule_lengthening_list_item() {
+        use tform::config::{CustomPattern, CustomRule, RuleAction};
+
+        let config = Config {
+            custom_patterns: vec![CustomPattern::Rule(CustomRule {
+                name: "todo".to_string(),
+                pattern: "^TODO (.*)$".to_string(),
+                action: RuleAction::Replace,
+                template: Some("- a much longer list item text $1".to_string()),
+                level: None,
+                priority: 0,
+            })],
+            ..Config::default()
+        };
+        let output = Formatter::new(config)
+            .format_to_markdown(Cursor::new("TODO x
+    nested under it
+"))
+            .expect("Failed to format rewritten list item");
+
+        assert_eq!(output, "- a much longer list item text x nested under it\n\n");
+    }
+
+    /// Test that invalid custom patterns are reported instead of silently ignored.
+    #[test]
+    fn test_invalid_custom_pattern() {
+        use tform::config::CustomPattern;
+
+        let config = Config {
+            custom_patterns: vec![CustomPattern::Pattern("(unclosed".to_string())],
+            ..Config::default()
+        };
+        assert!(Formatter::try_new(config).is_err(), "Invalid regex was accepted");
+    }
+
+    /// Test that `Formatter::new` leaves out invalid custom patterns and keeps the valid ones.
+    #[test]
+    fn test_new_skips_invalid_custom_pattern() {
+        use tform::config::CustomPattern;
+
+        let config = Config {
+            custom_patterns: vec![
+                CustomPattern::Pattern("(unclosed".to_string()),
+                CustomPattern::Pattern("^NB".to_string()),
+            ],
+            ..Config::default()
+        };
+        let output = Formatter::new(config)
+            .format_to_markdown(Cursor::new("NB mind the gap"))
+            .expect("Failed with an invalid custom pattern");
+        assert_eq!(output, "> [!CUSTOM]\n> NB mind the gap\n\n");
+    }
+
+    /// Test that a missing word list is reported as an I/O error naming the file.
+    #[test]
+    fn test_missing_word_list() {
+        use tform::errors::FormatterError;
+
+        let config = Config {
+            word_list: Some("no_such_words.txt".to_string()),
+            ..Config::default()
+        };
+        match Formatter::try_new(config) {
+            Err(e @ FormatterError::IoError(_)) => {
+                assert!(e.to_string().contains("no_such_words.txt"), "Path missing: {}", e)
+            }
+            Err(e) => panic!("Unexpected error: {:?}", e),
+            Ok(_) => panic!("Missing word list was accepted"),
+        }
+    }
+
+    /// Test that `Formatter::new` ignores a missing word list and keeps the built-in one.
+    #[test]
+    fn test_new_ignores_missing_word_list() {
+        let config = Config {
+            word_list: Some("no_such_words.txt".to_string()),
+            repair_split_words: true,
+            ..Config::default()
+        };
+        let output = Formatter::new(config)
+            .format_to_markdown(Cursor::new("wit\nhin reach"))
+            .expect("Failed with a missing word list");
+        assert_eq!(output, "within reach\n\n");
+    }
+
+    /// Test that the bundled config file (bare and table rules) loads and applies.
+    #[test]
+    fn test_config_file_custom_patterns() {
+        let config = Config::from_file("tform_config.toml").expect("Failed to load config");
+        let formatter = Formatter::try_new(config).expect("Invalid bundled config");
+
+        let output = formatter
+            .format_to_markdown(Cursor::new("NOTE: mind the gap\nTodo: write docs"))
+            .expect("Failed to format with config file");
+
+        assert!(output.contains("> [!NOTE]\n> mind the gap"), "Note rule missing: {}", output);
+        assert!(output.contains("> [!CUSTOM]\n> Todo: write docs"), "Todo rule missing: {}", output);
+    }
+
+    /// Test that numbered steps become an ordered list that keeps its start number.
+    #[test]
+    fn test_format_to_markdown_ordered_list() {
+        let formatter = Formatter::new(Config::default());
+
+        let input = "3. preheat\n4) mix\n5. bake\nEnjoy.";
+        let output = formatter
+            .format_to_markdown(Cursor::new(input))
+            .expect("Failed to format ordered list to Markdown");
+
+        assert!(
+            output.contains("3. preheat\n4. mix\n5. bake\n\nEnjoy."),
+            "Ordered list not rendered: {}",
+            output
+        );
+    }
+
+    /// Test that adjacent lists get different markers, streamed or not, so they stay apart.
+    #[test]
+    fn test_format_to_markdown_adjacent_lists() {
+        let formatter = Formatter::new(Config::default());
+
+        let input = "1. one\na. alpha\nb. beta\n\n3) three\n\n- dash\n\n* star\n";
+        let output = formatter
+            .format_to_markdown(Cursor::new(input))
+            .expect("Failed to format adjacent lists to Markdown");
+        assert_eq!(output, "1. one\n\n1) alpha\n2) beta\n\n3. three\n\n- dash\n\n+ star\n\n");
+
+        let mut streamed = Vec::new();
+        formatter
+            .format_markdown_to(Cursor::new(input), &mut streamed)
+            .expect("Failed to stream adjacent lists");
+        assert_eq!(String::from_utf8(streamed).unwrap(), output);
+
+        let again = formatter
+            .format_to_markdown(Cursor::new(output.as_str()))
+            .expect("Failed to reformat adjacent lists");
+        assert_eq!(again, output);
+    }
+
+    /// Test that alphabetic and roman enumerations map to `<ol>` types and starts.
+    #[test]
+    fn test_format_to_html_ordered_list_styles() {
+        let formatter = Formatter::new(Config::default());
+
+        let input = "a. first\nb. second\n\n(iv) fourth\n(v) fifth\n\nI. Intro\nII. Body";
+        let output = formatter
+            .format_to_html(Cursor::new(input))
+            .expect("Failed to format ordered lists to HTML");
+
+        assert!(output.contains("<ol type=\"a\">\n<li>first</li>\n<li>second</li>\n</ol>"), "{}", output);
+        assert!(output.contains("<ol type=\"i\" start=\"4\">\n<li>fourth</li>\n<li>fifth</li>"), "{}", output);
+        assert!(output.contains("<ol type=\"I\">\n<li>Intro</li>\n<li>Body</li>"), "{}", output);
+    }
+
+    /// Test that wrapped prose starting with a number or numeral stays in its paragraph.
+    #[test]
+    fn test_ordered_list_interrupting_paragraph() {
+        let formatter = Formatter::new(Config::default());
+        let format = |input: &str| formatter.format_to_markdown(Cursor::new(input)).expect("Failed to format");
+
+        assert_eq!(format("The trial ran until\n2019. The results"), "The trial ran until 2019. The results\n\n");
+        assert_eq!(format("As shown with\nI. Newton"), "As shown with I. Newton\n\n");
+        assert_eq!(format("The sample weighed\n5) kilograms"), "The sample weighed 5) kilograms\n\n");
+
+        // Lists starting at 1, a or i still interrupt, as do bullets
+        assert_eq!(format("Steps:\n1. mix\n2. bake"), "Steps:\n\n1. mix\n2. bake\n\n");
+        assert_eq!(format("Options:\na. yes\nb. no"), "Options:\n\n1. yes\n2. no\n\n");
+        assert_eq!(format("Parts:\ni. one\nii. two"), "Parts:\n\n1. one\n2. two\n\n");
+        assert_eq!(format("Items:\n- one"), "Items:\n\n- one\n\n");
+    }
+
+    /// Test that indented bullets nest, with tabs and spaces mixed.
+    #[test]
+    fn test_format_to_markdown_nested_list() {
+        let formatter = Formatter::new(Config::default());
+
+        let input = "- fruit\n  - apples\n\t- pears\n- veg\n\n  Leafy ones only.";
+        let output = formatter
+            .format_to_markdown(Cursor::new(input))
+            .expect("Failed to format nested list to Markdown");
+
+        assert!(
+            output.contains("- fruit\n  - apples\n    - pears\n- veg\n\n  Leafy ones only.\n"),
+            "Nested list not rendered: {}",
+            output
+        );
+    }
+
+    /// Test that a deeply nested list parses without copying its lines again
+    /// for every level.
+    #[test]
+    fn test_format_deeply_nested_list() {
+        let formatter = Formatter::new(Config::default());
+
+        let input: String = (0..3000).map(|i| format!("{}1. item\n", "  ".repeat(i))).collect();
+        let start = std::time::Instant::now();
+        let output = formatter
+            .format_to_markdown(Cursor::new(input))
+            .expect("Failed to format deeply nested list");
+
+        assert!(output.starts_with("1. item\n   1. item\n      1. item\n"), "{}", &output[..40]);
+        assert!(start.elapsed().as_secs() < 5, "took {:?}", start.elapsed());
+    }
+
+    /// Test that a long run of blank lines inside a list item is read in one pass.
+    #[test]
+    fn test_format_list_item_with_many_blank_lines() {
+        let formatter = Formatter::new(Config::default());
+
+        let input = format!("- item\n{}  more\n", "\n".repeat(100_000));
+        let start = std::time::Instant::now();
+        let output = formatter
+            .format_to_markdown(Cursor::new(input))
+            .expect("Failed to format list item with blank lines");
+
+        assert_eq!(output, "- item\n\n  more\n\n");
+        assert!(start.elapsed().as_secs() < 5, "took {:?}", start.elapsed());
+    }
+
+    /// Test that the item content column is measured before extra spaces are removed.
+    #[test]
+    fn test_list_item_code_with_wide_marker() {
+        let input = "-   a\n\n        code";
+        for remove_extra_spaces in [true, false] {
+            let formatter = Formatter::new(Config { remove_extra_spaces, ..Config::default() });
+            let output = formatter
+                .format_to_html(Cursor::new(input))
+                .expect("Failed to format list item code to HTML");
+
+            assert_eq!(output, "<ul>\n<li>a\n<pre><code>code</code></pre>\n</li>\n</ul>\n");
+        }
+    }
+
+    /// Test that nested lists end up inside their parent `<li>` in HTML.
+    #[test]
+    fn test_format_to_html_nested_list() {
+        let formatter = Formatter::new(Config::default());
+
+        let input = "1. step one\n   - detail\n2. step two";
+        let output = formatter
+            .format_to_html(Cursor::new(input))
+            .expect("Failed to format nested list to HTML");
+
+        assert!(
+            output.contains("<ol>\n<li>step one\n<ul>\n<li>detail</li>\n</ul>\n</li>\n<li>step two</li>\n</ol>"),
+            "Nested list not rendered: {}",
+            output
+        );
+    }
+
+    /// Test that inline markup becomes the matching HTML elements.
+    #[test]
+    fn test_format_to_html_inline_markup() {
+        let formatter = Formatter::new(Config::default());
+
+        let input = "# A *fancy* title\nSome *emph*, **strong**, `co*de*` and [a **link**](https://example.com).\n- see ![logo](logo.png)";
+        let output = formatter
+            .format_to_html(Cursor::new(input))
+            .expect("Failed to format inline markup to HTML");
+
+        assert!(output.contains("<h1>A <em>fancy</em> title</h1>"), "{}", output);
+        assert!(output.contains("<em>emph</em>, <strong>strong</strong>, <code>co*de*</code>"), "{}", output);
+        assert!(output.contains("<a href=\"https://example.com\">a <strong>link</strong></a>"), "{}", output);
+        assert!(output.contains("<li>see <img src=\"logo.png\" alt=\"logo\"></li>"), "{}", output);
+    }
+
+    /// Test that link urls may hold balanced parentheses.
+    #[test]
+    fn test_format_to_html_link_parentheses() {
+        let formatter = Formatter::new(Config::default());
+
+        let input = "[x](https://en.wikipedia.org/wiki/Rust_(lang)) [y](javascript:alert(1)) [z](a(b) [w](b(c )";
+        let output = formatter
+            .format_to_html(Cursor::new(input))
+            .expect("Failed to format links to HTML");
+
+        assert_eq!(
+            output,
+            "<p><a href=\"https://en.wikipedia.org/wiki/Rust_(lang)\">x</a> <a href=\"#\">y</a> [z](a(b) [w](b(c )</p>\n"
+        );
+    }
+
+    /// Test that a link inside another link's text leaves the outer one literal.
+    #[test]
+    fn test_format_to_html_nested_links() {
+        let formatter = Formatter::new(Config::default());
+
+        let input = "[a [b](c)](d) and [![img](i.png)](e)";
+        let output = formatter
+            .format_to_html(Cursor::new(input))
+            .expect("Failed to format nested links to HTML");
+
+        assert_eq!(
+            output,
+            "<p>[a <a href=\"c\">b</a>](d) and <a href=\"e\"><img src=\"i.png\" alt=\"img\"></a></p>\n"
+        );
+    }
+
+    /// Test that unpaired delimiters stay literal and hard breaks survive in Markdown.
+    #[test]
+    fn test_format_to_markdown_inline_markup() {
+        let formatter = Formatter::new(Config::default());
+
+        let input = "5 * 3 * 2 in snake_case_name, _emph_ here\\\nand ``a ` tick``";
+        let output = formatter
+            .format_to_markdown(Cursor::new(input))
+            .expect("Failed to format inline markup to Markdown");
+
+        assert_eq!(output, "5 * 3 * 2 in snake_case_name, *emph* here\\\nand ``a ` tick``\n\n");
+    }
+
+    /// Test that a long paragraph full of unpaired openers parses without
+    /// rescanning the rest of the paragraph for each one.
+    #[test]
+    fn test_unpaired_inline_openers_in_long_paragraph() {
+        let formatter = Formatter::new(Config::default());
+
+        for opener in ["*a ", "_a ", "[a ", "[a](b "] {
+            let input = opener.repeat(40_000);
+            let start = std::time::Instant::now();
+            let output = formatter
+                .format_to_html(Cursor::new(input.as_str()))
+                .expect("Failed to format long paragraph");
+
+            assert!(output.starts_with("<p>"), "{}", &output[..40]);
+            assert!(!output.contains("<em>") && !output.contains("<a "));
+            assert!(start.elapsed().as_secs() < 5, "{:?} took {:?}", opener, start.elapsed());
+        }
+    }
+
+    /// Test that markup-like text is escaped instead of injected into HTML.
+    #[test]
+    fn test_format_to_html_escapes_script_tags() {
+        let formatter = Formatter::new(Config::default());
+
+        let input = "# <script>alert(1)</script>\n- \"quoted\" & <b>bold</b>\n```\nif a < b && c > d {}\n```";
+        let output = formatter
+            .format_to_html(Cursor::new(input))
+            .expect("Failed to format to HTML");
+
+        assert!(!output.contains("<script>"), "Script tag injected: {}", output);
+        assert!(output.contains("<h1>&lt;script&gt;alert(1)&lt;/script&gt;</h1>"), "{}", output);
+        assert!(output.contains("<li>&quot;quoted&quot; &amp; &lt;b&gt;bold&lt;/b&gt;</li>"), "{}", output);
+        assert!(output.contains("<pre><code>if a &lt; b &amp;&amp; c &gt; d {}</code></pre>"), "{}", output);
+    }
+
+    /// Test that entities and already-escaped input are shown literally, not decoded.
+    #[test]
+    fn test_format_to_html_escapes_entities() {
+        let formatter = Formatter::new(Config::default());
+
+        let input = "AT&T says &amp; and &lt;tag&gt; and `&copy;`";
+        let output = formatter
+            .format_to_html(Cursor::new(input))
+            .expect("Failed to format to HTML");
+
+        assert_eq!(
+            output,
+            "<p>AT&amp;T says &amp;amp; and &amp;lt;tag&amp;gt; and <code>&amp;copy;</code></p>\n"
+        );
+    }
+
+    /// Test that attribute values are escaped and script URLs neutralized.
+    #[test]
+    fn test_format_to_html_escapes_attributes() {
+        use tform::config::CustomPattern;
+
+        let config = Config {
+            custom_patterns: vec![CustomPattern::Pattern("^NB".to_string())],
+            ..Config::default()
+        };
+        let formatter = Formatter::new(config);
+
+        let input = "[x](http://a.b/?q=\"><script>) ![it's](i.png) [y](JavaScript:alert(1))\n\nNB <i>";
+        let output = formatter
+            .format_to_html(Cursor::new(input))
+            .expect("Failed to format to HTML");
+
+        assert!(output.contains("<a href=\"http://a.b/?q=&quot;&gt;&lt;script&gt;\">x</a>"), "{}", output);
+        assert!(output.contains("<img src=\"i.png\" alt=\"it&#39;s\">"), "{}", output);
+        assert!(output.contains("<a href=\"#\">y</a>"), "Script URL kept: {}", output);
+        assert!(output.contains("<div class=\"callout callout-custom\">NB &lt;i&gt;</div>"), "{}", output);
+    }
+
+    /// Test that info strings, tilde fences and longer fences follow CommonMark.
+    #[test]
+    fn test_format_to_markdown_fenced_code_variants() {
+        let formatter = Formatter::new(Config::default());
+
+        let input = "```rust\nfn main() {}\n```\n~~~~ python\n```\nprint(1)\n~~~\n~~~~\n  ```\n  indented\n    deeper\n  ```";
+        let output = formatter
+            .format_to_markdown(Cursor::new(input))
+            .expect("Failed to format fenced code to Markdown");
+
+        assert!(output.contains("```rust\nfn main() {}\n```\n\n"), "{}", output);
+        // The inner ``` forces a longer fence on output
+        assert!(output.contains("````python\n```\nprint(1)\n~~~\n````\n\n"), "{}", output);
+        assert!(output.contains("```\nindented\n  deeper\n```\n\n"), "{}", output);
+    }
+
+    /// Test that the language becomes a `language-*` class in HTML.
+    #[test]
+    fn test_format_to_html_fenced_code_language() {
+        let formatter = Formatter::new(Config::default());
+
+        let input = "```rust ignore\nlet x = 1;\n```\n``` not`info\n";
+        let output = formatter
+            .format_to_html(Cursor::new(input))
+            .expect("Failed to format fenced code to HTML");
+
+        assert!(output.contains("<pre><code class=\"language-rust\">let x = 1;</code></pre>"), "{}", output);
+        assert!(output.contains("<p>``` not`info</p>"), "Backtick info string opened a fence: {}", output);
+    }
+
+    /// Test that indented runs become code blocks with their whitespace intact.
+    #[test]
+    fn test_format_to_html_indented_code() {
+        let formatter = Formatter::new(Config::default());
+
+        let input = "Log excerpt:\n\n    id   name    status\n\t1    web     up\n\n        (idle)\n\nA paragraph\n    wrapped with indentation.";
+        let output = formatter
+            .format_to_html(Cursor::new(input))
+            .expect("Failed to format indented code to HTML");
+
+        assert!(
+            output.contains("<pre><code>id   name    status\n1    web     up\n\n    (idle)</code></pre>"),
+            "Indented code not preserved: {}",
+            output
+        );
+        assert!(output.contains("<p>A paragraph wrapped with indentation.</p>"), "{}", output);
+    }
+
+    /// Test that a paragraph whose first line is indented stays a paragraph.
+    #[test]
+    fn test_indented_first_line_is_not_code() {
+        let formatter = Formatter::new(Config::default());
+
+        let input = "    It was a dark and stormy night; the rain fell
+in torrents, except at occasional intervals.
+
+    real code
+";
+        let output = formatter
+            .format_to_html(Cursor::new(input))
+            .expect("Failed to format indented paragraph to HTML");
+
+        assert!(
+            output.contains("<p>It was a dark and stormy night; the rain fell in torrents, except at occasional intervals.</p>"),
+            "{}",
+            output
+        );
+        assert!(output.contains("<pre><code>real code</code></pre>"), "{}", output);
+    }
+
+    /// Test that the indented code flag and threshold are honored.
+    #[test]
+    fn test_indented_code_config() {
+        let input = "  two  spaces\n\n    four";
+
+        let config = Config {
+            indented_code_width: 2,
+            ..Config::default()
+        };
+        let output = Formatter::new(config)
+            .format_to_markdown(Cursor::new(input))
+            .expect("Failed with custom threshold");
+        assert_eq!(output, "```\ntwo  spaces\n\n  four\n```\n\n");
+
+        let config = Config {
+            detect_indented_code: false,
+            ..Config::default()
+        };
+        let output = Formatter::new(config)
+            .format_to_markdown(Cursor::new(input))
+            .expect("Failed with indented code disabled");
+        assert_eq!(output, "two spaces\n\nfour\n\n");
+    }
+
+    /// Test that email-style nested quoting becomes nested blockquotes.
+    #[test]
+    fn test_format_to_html_nested_quotes() {
+        let formatter = Formatter::new(Config::default());
+
+        let input = "Bob wrote:\n> Can you send it?\n>\n>> Alice wrote:\n>> - the report\nSure.";
+        let output = formatter
+            .format_to_html(Cursor::new(input))
+            .expect("Failed to format quotes to HTML");
+
+        assert_eq!(
+            output,
+            "<p>Bob wrote:</p>\n<blockquote>\n<p>Can you send it?</p>\n<blockquote>\n<p>Alice wrote:</p>\n\
+             <ul>\n<li>the report</li>\n</ul>\n</blockquote>\n</blockquote>\n<p>Sure.</p>\n"
+        );
+    }
+
+    /// Test that quotes render with `>` prefixes, one per nesting level.
+    #[test]
+    fn test_format_to_markdown_quotes() {
+        let formatter = Formatter::new(Config::default());
+
+        let input = ">   Quoted   text\n> > inner";
+        let output = formatter
+            .format_to_markdown(Cursor::new(input))
+            .expect("Failed to format quotes to Markdown");
+
+        assert_eq!(output, "> Quoted text\n>\n> > inner\n\n");
+    }
+
+    /// Test that absurdly deep quote and list nesting is cut off instead of
+    /// overflowing the stack.
+    #[test]
+    fn test_format_deep_nesting() {
+        let formatter = Formatter::new(Config::default());
+
+        for input in [">".repeat(10_000), "- ".repeat(10_000) + "x"] {
+            let output = formatter
+                .format_to_html(Cursor::new(input))
+                .expect("Failed to format deeply nested input");
+            assert!(output.matches("<blockquote>").count() <= 100, "too many quotes");
+            assert!(output.matches("<ul>").count() <= 100, "too many lists");
+        }
+    }
+
+    /// Test that a GFM pipe table keeps its alignments and escaped pipes.
+    #[test]
+    fn test_format_to_markdown_pipe_table() {
+        let formatter = Formatter::new(Config::default());
+
+        let input = "Stock:\n| Name | Qty | Note |\n|:-----|----:|:----:|\n| apple | 3 | a \\| b |\n| pear | 10 |";
+        let output = formatter
+            .format_to_markdown(Cursor::new(input))
+            .expect("Failed to format pipe table to Markdown");
+
+        assert_eq!(
+            output,
+            "Stock:\n\n\
+             | Name  | Qty | Note   |\n\
+             | :---- | --: | :----: |\n\
+             | apple | 3   | a \\| b |\n\
+             | pear  | 10  |        |\n\n"
+        );
+    }
+
+    /// Test that space-aligned columns survive `remove_extra_spaces` as a table.
+    #[test]
+    fn test_format_to_html_aligned_table() {
+        let formatter = Formatter::new(Config::default());
+
+        let input = "id    name         price\n--    ----         -----\n1     Big  Widget  9.99\n2     Gadget       1,200";
+        let output = formatter
+            .format_to_html(Cursor::new(input))
+            .expect("Failed to format aligned table to HTML");
+
+        assert!(output.contains("<thead>\n<tr>\n<th style=\"text-align: right\">id</th>\n<th>name</th>"), "{}", output);
+        assert!(output.contains("<td>Big Widget</td>\n<td style=\"text-align: right\">9.99</td>"), "{}", output);
+        assert!(output.contains("<td style=\"text-align: right\">1,200</td>"), "{}", output);
+    }
+
+    /// Test that tab-separated rows and grid tables are detected, but prose is not.
+    #[test]
+    fn test_format_to_markdown_tab_and_grid_tables() {
+        let formatter = Formatter::new(Config::default());
+
+        let input = "a\tb\tc\n1\t2\n\n+----+-----+\n| id | val |\n+====+=====+\n| x  | y   |\n+----+-----+\n\nPick A | B today,\nthen  rest.";
+        let output = formatter
+            .format_to_markdown(Cursor::new(input))
+            .expect("Failed to format tables to Markdown");
+
+        assert!(output.contains("| a   | b   | c   |\n| --: | --: | --- |\n| 1   | 2   |     |\n"), "{}", output);
+        assert!(output.contains("| id  | val |\n| --- | --- |\n| x   | y   |\n"), "{}", output);
+        assert!(output.contains("Pick A | B today, then rest."), "{}", output);
+    }
+
+    /// Test that paragraph lines that look like rows stay in the paragraph, while a
+    /// delimiter row still turns the line above it into a table header.
+    #[test]
+    fn test_table_rows_inside_paragraph() {
+        let formatter = Formatter::new(Config::default());
+
+        let mut input = String::from("Notes follow.\n");
+        for _ in 0..500 {
+            input.push_str("a  b\nc\td\ne | f\n");
+        }
+        let output = formatter
+            .format_to_html(Cursor::new(input))
+            .expect("Failed to format paragraph to HTML");
+        assert_eq!(output.matches("<p>").count(), 1, "{}", output);
+        assert!(!output.contains("<table>"), "{}", output);
+
+        let input = "Notes follow.\n| a | b |\n|---|---|\n| 1 | 2 |";
+        let output = formatter
+            .format_to_html(Cursor::new(input))
+            .expect("Failed to format table to HTML");
+        assert!(output.starts_with("<p>Notes follow.</p>\n<table>"), "{}", output);
+        assert!(output.contains("<td>1</td>"), "{}", output);
+    }
+
+    /// Test that aligned list items, headings and two-space prose are not tables.
+    #[test]
+    fn test_aligned_blocks_are_not_tables() {
+        let formatter = Formatter::new(Config::default());
+
+        let input = "1. Step one    (5 min)\n2. Step two    (10 min)\n\n- apples   $1\n- pears    $2\n\n# Results   2020\nAlpha       Beta\n\nI went home.  Then I slept.\nIt was late.  I was tired.";
+        let output = formatter
+            .format_to_html(Cursor::new(input))
+            .expect("Failed to format aligned blocks to HTML");
+
+        assert!(!output.contains("<table>"), "{}", output);
+        assert!(output.contains("<ol>\n<li>Step one (5 min)</li>\n<li>Step two (10 min)</li>\n</ol>"), "{}", output);
+        assert!(output.contains("<ul>\n<li>apples $1</li>\n<li>pears $2</li>\n</ul>"), "{}", output);
+        assert!(output.contains("<h1>Results 2020</h1>\n<p>Alpha Beta</p>"), "{}", output);
+        assert!(output.contains("<p>I went home. Then I slept. It was late. I was tired.</p>"), "{}", output);
+    }
+
+    /// Test that thematic breaks are detected, even where they look like bullets.
+    #[test]
+    fn test_format_to_html_thematic_breaks() {
+        let formatter = Formatter::new(Config::default());
+
+        let input = "Intro\n***\n- a\n- - -\n- b\n____\n=====\n--";
+        let output = formatter
+            .format_to_html(Cursor::new(input))
+            .expect("Failed to format thematic breaks to HTML");
+
+        assert_eq!(
+            output,
+            "<p>Intro</p>\n<hr>\n<ul>\n<li>a</li>\n</ul>\n<hr>\n<ul>\n<li>b</li>\n</ul>\n<hr>\n<hr>\n<p>--</p>\n"
+        );
+    }
+
+    /// Test that thematic breaks render as `---` in Markdown.
+    #[test]
+    fn test_format_to_markdown_thematic_break() {
+        let formatter = Formatter::new(Config::default());
+
+        let output = formatter
+            .format_to_markdown(Cursor::new("above\n* * *\nbelow"))
+            .expect("Failed to format thematic break to Markdown");
+
+        assert_eq!(output, "above\n\n---\n\nbelow\n\n");
+    }
+
+    /// Test that underlined titles become headings.
+    #[test]
+    fn test_format_to_markdown_setext_headings() {
+        let formatter = Formatter::new(Config::default());
+
+        let input = "Report Title\n============\nintro text\n\nSection A\n---------\nThanks,\n--\nBob";
+        let output = formatter
+            .format_to_markdown(Cursor::new(input))
+            .expect("Failed to format setext headings to Markdown");
+
+        assert_eq!(output, "# Report Title\n\nintro text\n\n## Section A\n\nThanks, -- Bob\n\n");
+    }
+
+    /// Test that an underline below a multi-line paragraph promotes the whole paragraph.
+    #[test]
+    fn test_setext_heading_after_multi_line_paragraph() {
+        let formatter = Formatter::new(Config::default());
+
+        let output = formatter
+            .format_to_html(Cursor::new("Line one\nline two\n=====\nbody"))
+            .expect("Failed to format setext heading to HTML");
+        assert_eq!(output, "<h1>Line one line two</h1>\n<p>body</p>\n");
+
+        // A hard line break can't go in a heading, so the underline stays a rule
+        let output = formatter
+            .format_to_html(Cursor::new("Line one\\\nline two\n-----"))
+            .expect("Failed to format paragraph with a hard break to HTML");
+        assert_eq!(output, "<p>Line one<br>\nline two</p>\n<hr>\n");
+    }
+
+    /// Test that disabling setext headings leaves `---` as a thematic break.
+    #[test]
+    fn test_setext_headings_disabled() {
+        let config = Config {
+            detect_setext_headings: false,
+            ..Config::default()
+        };
+        let output = Formatter::new(config)
+            .format_to_html(Cursor::new("Section A\n---------"))
+            .expect("Failed with setext headings disabled");
+
+        assert_eq!(output, "<p>Section A</p>\n<hr>\n");
+    }
+
+    /// Test that the opt-in heuristics promote standalone unmarked lines to headings.
+    #[test]
+    fn test_heuristic_headings() {
+        let config = Config {
+            detect_all_caps_headings: true,
+            detect_title_case_headings: true,
+            detect_numbered_headings: true,
+            ..Config::default()
+        };
+        let formatter = Formatter::new(config);
+
+        let input = "EXECUTIVE SUMMARY\n\nSales rose.\n\n2.3 Results and Discussion\n\nNotes on the Release\n\nNOT A HEADING.\n\nThe Quick Brown Fox\njumps over the dog.";
+        let output = formatter
+            .format_to_markdown(Cursor::new(input))
+            .expect("Failed to format with heading heuristics");
+
+        assert_eq!(
+            output,
+            "# EXECUTIVE SUMMARY\n\nSales rose.\n\n## 2.3 Results and Discussion\n\n## Notes on the Release\n\n\
+             NOT A HEADING.\n\nThe Quick Brown Fox jumps over the dog.\n\n"
+        );
+    }
+
+    /// Test that each heading heuristic can be toggled on its own, and all are off by default.
+    #[test]
+    fn test_heuristic_headings_toggles() {
+        let input = "INTRODUCTION\n\nGetting Started\n\n1.2.1 Setup\n";
+
+        let output = Formatter::new(Config::default())
+            .format_to_markdown(Cursor::new(input))
+            .expect("Failed with default config");
+        assert!(!output.contains('#'), "Heuristics should be opt-in: {}", output);
+
+        let config = Config {
+            detect_numbered_headings: true,
+            ..Config::default()
+        };
+        let output = Formatter::new(config)
+            .format_to_markdown(Cursor::new(input))
+            .expect("Failed with numbered headings");
+        assert_eq!(output, "INTRODUCTION\n\nGetting Started\n\n### 1.2.1 Setup\n\n");
+    }
+
+    /// Test that a single-level number with a period stays a list item, while
+    /// the same title without the period becomes a heading.
+    #[test]
+    fn test_heuristic_headings_single_number() {
+        let config = Config {
+            detect_numbered_headings: true,
+            ..Config::default()
+        };
+        let output = Formatter::new(config)
+            .format_to_markdown(Cursor::new("1. Introduction\n\nText.\n\n2 Methods\n\nMore text.\n"))
+            .expect("Failed with numbered headings");
+        assert_eq!(output, "1. Introduction\n\nText.\n\n# 2 Methods\n\nMore text.\n\n");
+    }
+
+    /// Test that words hyphenated or split across lines are rejoined, keeping real compounds
+    /// and line breaks after real words.
+    #[test]
+    fn test_repair_broken_words() {
+        let formatter = Formatter::new(Config {
+            repair_split_words: true,
+            ..Config::default()
+        });
+
+        let input = "An exam-\nple of long-\nterm, self-\nevident fixes wit\nhin lines,\nnot in\nto words, moved to\nward three, gave me\nntor advice.";
+        let output = formatter
+            .format_to_markdown(Cursor::new(input))
+            .expect("Failed to repair broken words");
+
+        assert_eq!(
+            output,
+            "An example of long-term, self-evident fixes within lines, not in to words, moved to ward three, gave me ntor advice.\n\n"
+        );
+    }
+
+    /// Test that hyphens before a capital, a digit or after a compound prefix are kept.
+    #[test]
+    fn test_repair_hyphenation_keeps_compounds() {
+        let formatter = Formatter::new(Config::default());
+        let cases = [
+            ("Jean-\nPaul", "Jean-Paul"),
+            ("COVID-\n19", "COVID-19"),
+            ("Coca-\nCola", "Coca-Cola"),
+            ("anti-\ninflammatory", "anti-inflammatory"),
+            ("co-\nauthor", "co-author"),
+            ("pre-\norder", "pre-order"),
+            ("post-\nwar", "post-war"),
+            ("re-\nenter", "re-enter"),
+            ("semi-\nfinal", "semi-final"),
+            // Joining still wins where that gives a known word
+            ("re-\nport", "report"),
+        ];
+        for (input, expected) in cases {
+            let output = formatter
+                .format_to_markdown(Cursor::new(input))
+                .expect("Failed to repair hyphenation");
+            assert_eq!(output, format!("{}\n\n", expected), "{:?}", input);
+        }
+    }
+
+    /// Test that a user-supplied word list is consulted, and that the repairs can be disabled.
+    #[test]
+    fn test_repair_broken_words_config() {
+        let path = std::env::temp_dir().join(format!("tform_words_{}.txt", std::process::id()));
+        std::fs::write(&path, "# project vocabulary\ntokenizer\n").expect("Failed to write word list");
+
+        let input = "the token\nizer and exam-\nple";
+        let config = Config {
+            builtin_word_list: false,
+            word_list: Some(path.to_string_lossy().into_owned()),
+            repair_split_words: true,
+            ..Config::default()
+        };
+        let output = Formatter::new(config)
+            .format_to_markdown(Cursor::new(input))
+            .expect("Failed with user word list");
+        assert_eq!(output, "the tokenizer and example\n\n");
+
+        let config = Config {
+            repair_hyphenation: false,
+            repair_split_words: false,
+            ..Config::default()
+        };
+        let output = Formatter::new(config)
+            .format_to_markdown(Cursor::new(input))
+            .expect("Failed with repairs disabled");
+        assert_eq!(output, "the token izer and exam- ple\n\n");
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    /// Test that streaming to a writer produces the same output as the String APIs.
+    #[test]
+    fn test_format_to_writer_matches_string_output() {
+        let formatter = Formatter::new(Config::default());
+        let input = "# Title\n\nSome  text\nwrapped here.\n\n- one\n- two\n\n```\ncode\n```\n> quoted\n\n| a | b |\n|---|---|\n| 1 | 2 |\n";
+
+        let mut markdown = Vec::new();
+        formatter
+            .format_markdown_to(Cursor::new(input), &mut markdown)
+            .expect("Failed to stream Markdown");
+        let expected = formatter
+            .format_to_markdown(Cursor::new(input))
+            .expect("Failed to format to Markdown");
+        assert_eq!(String::from_utf8(markdown).unwrap(), expected);
+
+        let mut html = Vec::new();
+        formatter
+            .format_html_to(Cursor::new(input), &mut html)
+            .expect("Failed to stream HTML");
+        let expected = formatter
+            .format_to_html(Cursor::new(input))
+            .expect("Failed to format to HTML");
+        assert_eq!(String::from_utf8(html).unwrap(), expected);
+    }
+
+    /// Test that `parse_iter` yields finished blocks before the rest of the input is read.
+    #[test]
+    fn test_parse_iter_yields_blocks_incrementally() {
+        use tform::parser::Parser;
+        use tform::structures::{Block, Spanned};
+
+        // The invalid UTF-8 at the end is only hit once the earlier blocks are out
+        let mut input = b"# Title\n\nFirst paragraph.\n\n".to_vec();
+        input.extend_from_slice(&[0xff, 0xfe, b'\n']);
+
+        let parser = Parser::new(Config::default());
+        let mut blocks = parser.parse_iter(Cursor::new(input));
+        assert!(matches!(blocks.next(), Some(Ok(Spanned { node: Block::Heading(1, _), .. }))));
+        assert!(matches!(blocks.next(), Some(Ok(Spanned { node: Block::Paragraph(_), .. }))));
+        assert!(matches!(blocks.next(), Some(Err(_))));
+        assert!(blocks.next().is_none());
+    }
+
+    /// Test that a failing writer surfaces as `FormatterError::WriteError`.
+    #[test]
+    fn test_format_to_writer_reports_write_errors() {
+        use std::io::{self, Write};
+        use tform::errors::FormatterError;
+
+        struct FailingWriter;
+        impl Write for FailingWriter {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let formatter = Formatter::new(Config::default());
+        let result = formatter.format_markdown_to(Cursor::new("# Title\n"), FailingWriter);
+        assert!(matches!(result, Err(FormatterError::WriteError(_))), "{:?}", result);
+        let result = formatter.format_html_to(Cursor::new("# Title\n"), FailingWriter);
+        assert!(matches!(result, Err(FormatterError::WriteError(_))), "{:?}", result);
+
+        // The renderers can also be driven directly from parsed blocks
+        let doc = tform::parser::Parser::new(Config::default())
+            .parse(Cursor::new("# Title\n"))
+            .expect("Failed to parse");
+        let mut html = Vec::new();
+        tform::formatter::write_html(&doc.blocks, &mut html).expect("Failed to write HTML");
+        assert_eq!(String::from_utf8(html).unwrap(), "<h1>Title</h1>\n");
+    }
+
+    /// Test that a user-defined `Renderer` receives every block between the document hooks.
+    #[test]
+    fn test_format_with_custom_renderer() {
+        use std::io::{self, Write};
+        use tform::renderer::{MarkdownRenderer, Renderer};
+        use tform::structures::{Block, Inline, Spanned};
+
+        // A tiny wiki-markup renderer, as a downstream crate might write
+        struct WikiRenderer;
+        impl Renderer for WikiRenderer {
+            fn document_start(&self, output: &mut dyn Write) -> io::Result<()> {
+                writeln!(output, "{{toc}}")
+            }
+            fn block(&self, block: &Spanned<Block>, output: &mut dyn Write) -> io::Result<()> {
+                let text = |inlines: &[Inline]| -> String {
+                    inlines
+                        .iter()
+                        .map(|i| match i {
+                            Inline::Text(t) => t.clone(),
+                            _ => String::new(),
+                        })
+                        .collect()
+                };
+                match &block.node {
+                    Block::Heading(level, inlines) => writeln!(output, "h{}. {}", level, text(inlines)),
+                    Block::Paragraph(inlines) => writeln!(output, "{}", text(inlines)),
+                    Block::List(items) => items
+                        .iter()
+                        .try_for_each(|item| writeln!(output, "* {}", text(&item.text))),
+                    _ => Ok(()),
+                }
+            }
+            fn document_end(&self, output: &mut dyn Write) -> io::Result<()> {
+                writeln!(output, "----")
+            }
+        }
+
+        let formatter = Formatter::new(Config::default());
+        let input = "# Title\nSome   text.\n\n- one\n- two\n";
+        let output = formatter
+            .format_with(&WikiRenderer, Cursor::new(input))
+            .expect("Failed to format with custom renderer");
+        assert_eq!(output, "{toc}\nh1. Title\nSome text.\n* one\n* two\n----\n");
+
+        // The built-in renderers go through the same entry point
+        let output = formatter
+            .format_with(&MarkdownRenderer::default(), Cursor::new(input))
+            .expect("Failed to format with Markdown renderer");
+        let expected = formatter
+            .format_to_markdown(Cursor::new(input))
+            .expect("Failed to format to Markdown");
+        assert_eq!(output, expected);
+    }
+
+    /// Test that custom detectors run in priority order alongside the built-in ones.
+    #[test]
+    fn test_custom_block_detector() {
+        use tform::detector::{priority, BlockDetector, DetectContext, Detection};
+        use tform::errors::FormatterError;
+        use tform::structures::{Block, Spanned};
+
+        // "::: name" ... ":::" containers, whose contents are parsed as blocks
+        struct ContainerDetector;
+        impl BlockDetector for ContainerDetector {
+            fn priority(&self) -> i32 {
+                priority::FENCED_CODE + 1
+            }
+            fn detect(&self, ctx: &mut DetectContext) -> Result<Option<Detection>, FormatterError> {
+                let Some(name) = ctx.text().strip_prefix(":::").map(str::trim) else {
+                    return Ok(None);
+                };
+                let name = name.to_string();
+                let mut body = vec![];
+                while let Some(line) = ctx.next_line()? {
+                    if line.text == ":::" {
+                        break;
+                    }
+                    body.push(line);
+                }
+                let title = Block::Heading(3, ctx.parse_inlines(&name));
+                let mut blocks = vec![Spanned::new(title, ctx.line().span())];
+                blocks.extend(ctx.parse_nested(body)?);
+                Ok(Some(Detection::Block(Block::Quote(blocks))))
+            }
+        }
+
+        // Years at the start of a line are prose, not numbered list items
+        struct YearDetector;
+        impl BlockDetector for YearDetector {
+            fn priority(&self) -> i32 {
+                priority::LIST + 1
+            }
+            fn detect(&self, ctx: &mut DetectContext) -> Result<Option<Detection>, FormatterError> {
+                let is_year = ctx
+                    .text()
+                    .split_once(". ")
+                    .is_some_and(|(n, _)| n.len() == 4 && n.chars().all(|c| c.is_ascii_digit()));
+                Ok(is_year.then(|| Detection::Paragraph(ctx.text().to_string())))
+            }
+        }
+
+        let mut formatter = Formatter::new(Config::default());
+        formatter.add_detector(ContainerDetector);
+        formatter.add_detector(YearDetector);
+
+        let input = "::: Warning\nMind the  gap.\n\n- one\n:::\nIt began in\n1990. Then more.\n";
+        let output = formatter
+            .format_to_markdown(Cursor::new(input))
+            .expect("Failed to format with custom detectors");
+        assert_eq!(
+            output,
+            "> ### Warning\n>\n> Mind the gap.\n>\n> - one\n\nIt began in 1990. Then more.\n\n"
+        );
+    }
+
+    /// Test that blocks, including nested ones, carry their position in the input.
+    #[test]
+    fn test_block_source_spans() {
+        use tform::parser::Parser;
+        use tform::structures::{Block, Position};
+
+        let input = "Title\r\n=====\r\n\r\n  Some  text\r\nwrapped.\r\n> quoted\r\n> - item\r\n";
+        let doc = Parser::new(Config::default())
+            .parse(Cursor::new(input))
+            .expect("Failed to parse");
+        let pos = |offset, line, column| Position { offset, line, column };
+
+        // A setext heading spans its underline; CRLF line endings count as two bytes
+        assert_eq!(doc.blocks[0].span.start, pos(0, 1, 1));
+        assert_eq!(doc.blocks[0].span.end, pos(12, 2, 6));
+        // Paragraphs start at their first non-blank character
+        assert_eq!(doc.blocks[1].span.start, pos(18, 4, 3));
+        assert_eq!(doc.blocks[1].span.end, pos(38, 5, 9));
+
+        let Block::Quote(inner) = &doc.blocks[2].node else {
+            panic!("expected a quote: {:?}", doc.blocks[2]);
+        };
+        assert_eq!(doc.blocks[2].span.start, pos(40, 6, 1));
+        // Nested blocks point past the quote marker
+        assert_eq!(inner[0].span.start, pos(42, 6, 3));
+        assert_eq!(inner[1].span.start, pos(52, 7, 3));
+    }
+
+    /// Test that HTML output can mark blocks with their source line.
+    #[test]
+    fn test_format_to_html_source_lines() {
+        let config = Config {
+            html_source_lines: true,
+            ..Config::default()
+        };
+        let formatter = Formatter::new(config);
+        let input = "# Title\n\ntext\n\n- one\n\n  nested\n";
+        let expected = "<h1 data-source-line=\"1\">Title</h1>\n\
+                        <p data-source-line=\"3\">text</p>\n\
+                        <ul data-source-line=\"5\">\n\
+                        <li>one\n<p data-source-line=\"7\">nested</p>\n</li>\n\
+                        </ul>\n";
+
+        let output = formatter
+            .format_to_html(Cursor::new(input))
+            .expect("Failed to format to HTML");
+        assert_eq!(output, expected);
+
+        let mut streamed = Vec::new();
+        formatter
+            .format_html_to(Cursor::new(input), &mut streamed)
+            .expect("Failed to stream HTML");
+        assert_eq!(String::from_utf8(streamed).unwrap(), expected);
+
+        // Off by default
+        let output = Formatter::new(Config::default())
+            .format_to_html(Cursor::new(input))
+            .expect("Failed to format to HTML");
+        assert!(!output.contains("data-source-line"), "{}", output);
+    }
+
+    /// Test the JSON output: a versioned, tagged tree that deserializes back into a `Document`.
+    #[test]
+    fn test_format_to_json() {
+        use tform::parser::Parser;
+        use tform::structures::{Block, Document};
+
+        let formatter = Formatter::new(Config::default());
+        let input = "# Hi *there*\n\n> - one\n\n| a | b |\n|:--|--:|\n| 1 | 2 |\n";
+        let json = formatter
+            .format_to_json(Cursor::new(input))
+            .expect("Failed to format to JSON");
+
+        let value: serde_json::Value = serde_json::from_str(&json).expect("Invalid JSON");
+        assert_eq!(value["version"], 1);
+        let heading = &value["blocks"][0];
+        assert_eq!(heading["type"], "heading");
+        assert_eq!(heading["level"], 1);
+        assert_eq!(heading["content"][0], serde_json::json!({"type": "text", "text": "Hi "}));
+        assert_eq!(heading["content"][1]["type"], "emphasis");
+        assert_eq!(heading["span"]["start"], serde_json::json!({"offset": 0, "line": 1, "column": 1}));
+        let quote = &value["blocks"][1];
+        assert_eq!(quote["type"], "quote");
+        assert_eq!(quote["blocks"][0]["type"], "list");
+        assert_eq!(quote["blocks"][0]["items"][0]["content"][0]["text"], "one");
+        let table = &value["blocks"][2];
+        assert_eq!(table["type"], "table");
+        assert_eq!(table["alignments"], serde_json::json!(["left", "right"]));
+
+        // Streaming gives the same output as serializing the parsed document
+        let doc = Parser::new(Config::default())
+            .parse(Cursor::new(input))
+            .expect("Failed to parse");
+        assert_eq!(json, serde_json::to_string(&doc).unwrap());
+
+        let mut streamed = Vec::new();
+        formatter
+            .format_json_to(Cursor::new(input), &mut streamed)
+            .expect("Failed to stream JSON");
+        assert_eq!(String::from_utf8(streamed).unwrap(), json);
+
+        // The tree deserializes back and re-serializes identically
+        let parsed: Document = serde_json::from_str(&json).expect("Failed to deserialize");
+        assert!(matches!(parsed.blocks[0].node, Block::Heading(1, _)));
+        assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+
+        // Other schema versions are rejected
+        let result = serde_json::from_str::<Document>(r#"{"version":99,"blocks":[]}"#);
+        assert!(result.is_err());
+    }
+
+    /// Test that one parsed `Document` renders the same as formatting the input directly.
+    #[test]
+    fn test_render_parsed_document() {
+        use tform::renderer::JsonRenderer;
+
+        let formatter = Formatter::new(Config::default());
+        let input = "# Title\n\nSome   text.\n\n1. one\n2. two\n\n> quoted\n";
+        let doc = formatter.parse(Cursor::new(input)).expect("Failed to parse");
+
+        let markdown = formatter
+            .format_to_markdown(Cursor::new(input))
+            .expect("Failed to format to Markdown");
+        assert_eq!(formatter.render_markdown(&doc), markdown);
+
+        let html = formatter
+            .format_to_html(Cursor::new(input))
+            .expect("Failed to format to HTML");
+        assert_eq!(formatter.render_html(&doc), html);
+
+        let json = formatter
+            .format_to_json(Cursor::new(input))
+            .expect("Failed to format to JSON");
+        let rendered = formatter
+            .render_with(&JsonRenderer::default(), &doc)
+            .expect("Failed to render JSON");
+        assert_eq!(rendered, json);
+    }
+
+    /// Test that a renderer failing on a parsed document surfaces as `FormatterError::WriteError`.
+    #[test]
+    fn test_render_with_reports_renderer_errors() {
+        use std::io::{self, Write};
+        use tform::errors::FormatterError;
+        use tform::structures::{Block, Spanned};
+
+        struct FailingRenderer;
+        impl tform::renderer::Renderer for FailingRenderer {
+            fn block(&self, _: &Spanned<Block>, _: &mut dyn Write) -> io::Result<()> {
+                Err(io::Error::other("renderer failed"))
+            }
+        }
+
+        let formatter = Formatter::new(Config::default());
+        let doc = formatter.parse(Cursor::new("# Title\n")).expect("Failed to parse");
+        let result = formatter.render_with(&FailingRenderer, &doc);
+        assert!(matches!(result, Err(FormatterError::WriteError(_))), "{:?}", result);
+    }
 }